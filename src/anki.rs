@@ -1,28 +1,327 @@
-use ankiconnect_rs::{AnkiClient, Deck, Model, models::FieldRef};
-use anyhow::{Result, anyhow};
+use std::{
+    collections::{BTreeSet, HashMap},
+    time::Duration,
+};
 
-/// Finds a deck by name
-///
-/// If a deck named `"Default"` is requested and it doesn’t exist,
-/// a new `"Default"` deck will be created automatically.
-/// For all other names, an error is returned if the deck is not found.
-pub fn find_deck(client: &AnkiClient, name: &str) -> Result<Deck> {
-    Ok(client
-        .decks()
-        .get_all()?
-        .into_iter()
-        .find(|d| d.name() == name)
-        .ok_or_else(|| anyhow!("Deck '{}' not found", name))?)
+use ankiconnect_rs::{Deck, Field, Model, Note, builders::Query, models::FieldRef};
+use anyhow::{Context, Result, anyhow};
+use futures::future::join_all;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+
+use crate::config::AppConfig;
+use crate::duplicates::DuplicateScope;
+use crate::error::ErrorKind;
+
+pub const DEFAULT_URL: &str = "http://127.0.0.1:8765";
+pub const DEFAULT_TIMEOUT_MS: u64 = 15_000;
+
+/// AnkiConnect API version requests are written against.
+const API_VERSION: u16 = 6;
+
+/// Talks to the AnkiConnect add-on, possibly on another machine and behind
+/// its `apiKey` setting.
+pub struct AnkiConnect {
+    http: reqwest::Client,
+    url: String,
+    api_key: Option<String>,
+}
+
+impl AnkiConnect {
+    pub fn new(url: &str, api_key: Option<String>, timeout: Duration) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .context("failed to build the AnkiConnect client")?;
+        Ok(Self {
+            http,
+            url: url.trim_end_matches('/').to_string(),
+            api_key: api_key.filter(|key| !key.is_empty()),
+        })
+    }
+
+    /// Client for the endpoint, API key and timeout in `config`.
+    pub fn from_config(config: &AppConfig) -> Result<Self> {
+        Self::new(
+            config.anki_url.as_deref().unwrap_or(DEFAULT_URL),
+            config.anki_api_key.clone(),
+            Duration::from_millis(config.anki_timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS)),
+        )
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Calls an AnkiConnect action. Transport failures are tagged
+    /// [`ErrorKind::AnkiUnreachable`]; errors AnkiConnect reports are not.
+    pub async fn invoke<R: DeserializeOwned>(&self, action: &str, params: Value) -> Result<R> {
+        let mut request = json!({ "action": action, "version": API_VERSION });
+        if !params.is_null() {
+            request["params"] = params;
+        }
+        if let Some(key) = &self.api_key {
+            request["key"] = key.as_str().into();
+        }
+
+        let response = self
+            .http
+            .post(&self.url)
+            .json(&request)
+            .send()
+            .await
+            .map_err(|err| transport_error(&self.url, &err))?;
+        let reply: Value = response
+            .json()
+            .await
+            .map_err(|err| transport_error(&self.url, &err))?;
+
+        match reply.get("error") {
+            Some(Value::String(error)) => Err(anyhow!("AnkiConnect {action} failed: {error}")),
+            _ => serde_json::from_value(reply.get("result").cloned().unwrap_or(Value::Null))
+                .with_context(|| format!("unexpected AnkiConnect {action} response")),
+        }
+    }
+
+    /// Checks that AnkiConnect answers, accepts our API key and is new enough,
+    /// turning each failure into an explanation rather than a client error.
+    pub async fn check(&self) -> Result<u16> {
+        let version = match self.invoke::<u16>("version", Value::Null).await {
+            Ok(version) => version,
+            Err(err) if ErrorKind::of(&err).is_some() => return Err(err),
+            Err(err) if err.to_string().to_lowercase().contains("api key") => {
+                return Err(anyhow::Error::new(ErrorKind::AnkiRejected).context(format!(
+                    "AnkiConnect at {} rejected the request: set anki_api_key to its apiKey setting",
+                    self.url
+                )));
+            }
+            Err(err) => return Err(err),
+        };
+        if version < API_VERSION {
+            return Err(anyhow::Error::new(ErrorKind::AnkiRejected).context(format!(
+                "AnkiConnect at {} is version {version}, too old; version {API_VERSION} or newer is required",
+                self.url
+            )));
+        }
+        Ok(version)
+    }
+
+    pub async fn find_cards(&self, query: &Query) -> Result<Vec<u64>> {
+        self.invoke("findCards", json!({ "query": query.as_str() }))
+            .await
+    }
+
+    pub async fn find_notes(&self, query: &Query) -> Result<Vec<u64>> {
+        self.invoke("findNotes", json!({ "query": query.as_str() }))
+            .await
+    }
+
+    pub async fn notes_info(&self, ids: &[u64]) -> Result<Vec<NoteInfo>> {
+        self.invoke("notesInfo", json!({ "notes": ids })).await
+    }
+
+    /// The first field of every note type, without repeats.
+    pub async fn first_field_names(&self) -> Result<Vec<String>> {
+        let models: HashMap<String, u64> = self.invoke("modelNamesAndIds", Value::Null).await?;
+        let fields = join_all(models.keys().map(|name| {
+            self.invoke::<Vec<String>>("modelFieldNames", json!({ "modelName": name }))
+        }))
+        .await;
+
+        let mut names = BTreeSet::new();
+        for result in fields {
+            if let Some(first) = result?.into_iter().next() {
+                names.insert(first);
+            }
+        }
+        Ok(names.into_iter().collect())
+    }
+
+    /// Adds a note, returning `None` when Anki refuses it as a duplicate
+    /// within the deck.
+    pub async fn add_note(
+        &self,
+        deck: &Deck,
+        note: &Note,
+        scope: DuplicateScope,
+    ) -> Result<Option<u64>> {
+        let params = json!({ "note": note_json(deck, note, scope) });
+        match self.invoke("addNote", params).await {
+            Ok(id) => Ok(Some(id)),
+            Err(err) if is_duplicate_error(&err.to_string()) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Asks Anki whether each note could be added, without adding any; the
+    /// error explains a refusal.
+    pub async fn can_add_notes(
+        &self,
+        deck: &Deck,
+        notes: &[&Note],
+        scope: DuplicateScope,
+    ) -> Result<Vec<std::result::Result<(), String>>> {
+        #[derive(serde::Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Verdict {
+            can_add: bool,
+            error: Option<String>,
+        }
+
+        let notes: Vec<Value> = notes
+            .iter()
+            .map(|note| note_json(deck, note, scope))
+            .collect();
+        let verdicts: Vec<Verdict> = self
+            .invoke("canAddNotesWithErrorDetail", json!({ "notes": notes }))
+            .await?;
+        Ok(verdicts
+            .into_iter()
+            .map(|verdict| match verdict {
+                Verdict { can_add: true, .. } => Ok(()),
+                Verdict { error, .. } => Err(error.unwrap_or_else(|| "refused".to_string())),
+            })
+            .collect())
+    }
+
+    pub async fn delete_notes(&self, ids: &[u64]) -> Result<()> {
+        self.invoke::<Value>("deleteNotes", json!({ "notes": ids }))
+            .await
+            .map(drop)
+    }
+
+    /// Replaces `tag` with `replacement` on the given notes.
+    pub async fn replace_tags(&self, notes: &[u64], tag: &str, replacement: &str) -> Result<()> {
+        let params = json!({
+            "notes": notes,
+            "tag_to_replace": tag,
+            "replace_with_tag": replacement,
+        });
+        self.invoke::<Value>("replaceTags", params).await.map(drop)
+    }
+
+    /// Adds notes in one request; an entry is `None` when Anki refused that
+    /// note.
+    pub async fn add_notes(
+        &self,
+        deck: &Deck,
+        notes: &[&Note],
+        scope: DuplicateScope,
+    ) -> Result<Vec<Option<u64>>> {
+        let notes: Vec<Value> = notes
+            .iter()
+            .map(|note| note_json(deck, note, scope))
+            .collect();
+        self.invoke("addNotes", json!({ "notes": notes })).await
+    }
+}
+
+/// The parts of an AnkiConnect `notesInfo` entry we read.
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoteInfo {
+    pub note_id: u64,
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub fields: HashMap<String, NoteField>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct NoteField {
+    pub value: String,
+    pub order: usize,
+}
+
+impl NoteInfo {
+    /// Value of the note type's first field.
+    pub fn first_field(&self) -> Option<&str> {
+        self.fields
+            .values()
+            .min_by_key(|field| field.order)
+            .map(|field| field.value.as_str())
+    }
+}
+
+fn note_json(deck: &Deck, note: &Note, scope: DuplicateScope) -> Value {
+    json!({
+        "deckName": deck.name(),
+        "modelName": note.model().name(),
+        "fields": note.field_values(),
+        "tags": note.tags(),
+        "options": scope.note_options(deck.name()),
+    })
+}
+
+pub fn is_duplicate_error(error: &str) -> bool {
+    error.to_lowercase().contains("duplicate")
+}
+
+/// Classifies a failed request. Something other than AnkiConnect answering
+/// is a misconfigured URL rather than an unreachable Anki, so it is not
+/// worth queueing notes for.
+fn transport_error(url: &str, err: &reqwest::Error) -> anyhow::Error {
+    if err.is_decode() {
+        return anyhow::Error::new(ErrorKind::AnkiRejected).context(format!(
+            "{url} did not answer like AnkiConnect; check anki_url"
+        ));
+    }
+    let reason = if err.is_connect() {
+        "Anki is not running there, or the AnkiConnect add-on is not installed".to_string()
+    } else if err.is_timeout() {
+        "the request timed out".to_string()
+    } else {
+        format!("{err}")
+    };
+    anyhow::Error::new(ErrorKind::AnkiUnreachable)
+        .context(format!("AnkiConnect at {url} is unreachable: {reason}"))
+}
+
+/// Finds a deck by name, failing with [`ErrorKind::MissingDeckOrModel`] when
+/// it does not exist.
+pub async fn find_deck(client: &AnkiConnect, name: &str) -> Result<Deck> {
+    let decks: HashMap<String, u64> = client.invoke("deckNamesAndIds", Value::Null).await?;
+    decks
+        .get(name)
+        .map(|&id| Deck::new(id, name.to_string()))
+        .ok_or_else(|| {
+            anyhow::Error::new(ErrorKind::MissingDeckOrModel)
+                .context(format!("Deck '{}' not found", name))
+        })
 }
 
 /// Find a model by name.
-pub fn find_model(client: &AnkiClient, name: &str) -> Result<Model> {
-    Ok(client
-        .models()
-        .get_all()?
+pub async fn find_model(client: &AnkiConnect, name: &str) -> Result<Model> {
+    let models: HashMap<String, u64> = client.invoke("modelNamesAndIds", Value::Null).await?;
+    let Some(&id) = models.get(name) else {
+        return Err(anyhow::Error::new(ErrorKind::MissingDeckOrModel)
+            .context(format!("Model '{}' not found", name)));
+    };
+    let fields: Vec<String> = client
+        .invoke("modelFieldNames", json!({ "modelName": name }))
+        .await?;
+    let fields = fields
         .into_iter()
-        .find(|m| m.name() == name)
-        .ok_or_else(|| anyhow!("Model '{}' not found", name))?)
+        .enumerate()
+        .map(|(ord, field)| Field::new(field, ord))
+        .collect();
+    Ok(Model::new(id, name.to_string(), fields)?)
+}
+
+/// Stand-in for a model that cannot be fetched while Anki is unreachable,
+/// with just the fields the pipeline writes to.
+pub fn offline_model(name: &str, fields: &[&str]) -> Result<Model> {
+    let mut unique: Vec<&str> = Vec::new();
+    for field in fields {
+        if !unique.contains(field) {
+            unique.push(field);
+        }
+    }
+    let fields = unique
+        .into_iter()
+        .enumerate()
+        .map(|(ord, field)| Field::new(field.to_string(), ord))
+        .collect();
+    Ok(Model::new(0, name.to_string(), fields)?)
 }
 
 /// Get a field from the model by name, or return an error if it doesn't exist.
@@ -32,3 +331,55 @@ pub fn get_model_field<'a>(model: &'a Model, name: &str) -> Result<FieldRef<'a>>
         .field_ref(name)
         .ok_or_else(|| anyhow!("Missing '{}' field", name))
 }
+
+/// Upload base64 `data` to Anki's media folder and return the name Anki
+/// stored it under.
+///
+/// The data is sent inline so this works even when Anki can't see our filesystem.
+pub async fn store_media(client: &AnkiConnect, filename: &str, data: &str) -> Result<String> {
+    let params = json!({
+        "filename": filename,
+        "data": data,
+        "deleteExisting": true,
+    });
+    client.invoke("storeMediaFile", params).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reports_a_closed_port_as_anki_not_running() {
+        let client = AnkiConnect::new("http://127.0.0.1:9/", None, Duration::from_secs(2)).unwrap();
+        assert_eq!(client.url(), "http://127.0.0.1:9");
+
+        let err = client.check().await.unwrap_err();
+        assert_eq!(ErrorKind::of(&err), Some(ErrorKind::AnkiUnreachable));
+        assert!(err.to_string().contains("Anki is not running"));
+    }
+
+    #[tokio::test]
+    async fn tells_rejections_apart_from_unreachable() {
+        async fn serve(reply: Value) -> String {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let router = axum::Router::new().route(
+                "/",
+                axum::routing::post(move || async move { axum::Json(reply) }),
+            );
+            tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+            url
+        }
+
+        for reply in [
+            json!({ "result": null, "error": "valid api key must be provided" }),
+            json!({ "result": 5, "error": null }),
+        ] {
+            let url = serve(reply).await;
+            let client = AnkiConnect::new(&url, None, Duration::from_secs(2)).unwrap();
+            let err = client.check().await.unwrap_err();
+            assert_eq!(ErrorKind::of(&err), Some(ErrorKind::AnkiRejected));
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// File name of the health record inside the cache directory.
+pub const HEALTH_FILE: &str = "translation-health.json";
+
+/// Consecutive failures before a base is taken out of rotation.
+const FAILURES_BEFORE_EJECT: u32 = 3;
+const FAILURE_EJECT: Duration = Duration::from_secs(120);
+const MAX_EJECT: Duration = Duration::from_secs(60 * 60);
+const RATE_LIMIT_EJECT: Duration = Duration::from_secs(60);
+
+/// Assumed latency for bases we have not timed yet, so they rank behind
+/// known-fast ones but ahead of known-slow ones.
+const UNKNOWN_LATENCY_MS: f64 = 1_500.0;
+/// Weight of the newest sample in the latency moving average.
+const LATENCY_WEIGHT: f64 = 0.3;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct BaseStats {
+    /// Moving average of successful request latency.
+    latency_ms: Option<f64>,
+    successes: u64,
+    failures: u64,
+    rate_limited: u64,
+    consecutive_failures: u32,
+    /// Unix time until which the base is skipped.
+    ejected_until: Option<u64>,
+}
+
+impl BaseStats {
+    fn is_ejected(&self, now: u64) -> bool {
+        self.ejected_until.is_some_and(|until| until > now)
+    }
+}
+
+/// Per-base latency and failure record, shared across lookups and kept in the
+/// cache directory between runs.
+#[derive(Default)]
+pub struct BaseHealth {
+    path: Option<PathBuf>,
+    stats: Mutex<HashMap<String, BaseStats>>,
+}
+
+impl BaseHealth {
+    /// Loads the record at `path`; a missing or unreadable file starts fresh.
+    pub fn load(path: PathBuf) -> Self {
+        let stats = fs::read_to_string(&path)
+            .ok()
+            .and_then(|raw| serde_json::from_str(&raw).ok())
+            .unwrap_or_default();
+        Self {
+            path: Some(path),
+            stats: Mutex::new(stats),
+        }
+    }
+
+    /// Writes the record back, if it was loaded from a file.
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let raw = serde_json::to_string_pretty(&*self.lock())?;
+        write_atomic(path, &raw).with_context(|| format!("failed to write '{}'", path.display()))
+    }
+
+    /// Indexes of `bases` in the order they should be tried: healthy bases by
+    /// failures then latency, keeping the configured order on ties. Ejected
+    /// bases are left out unless every base is ejected.
+    pub fn order(&self, bases: &[&str]) -> Vec<usize> {
+        let stats = self.lock();
+        let now = unix_now();
+        let default = BaseStats::default();
+        let stats_of = |index: usize| stats.get(bases[index]).unwrap_or(&default);
+
+        let mut order: Vec<usize> = (0..bases.len())
+            .filter(|&index| !stats_of(index).is_ejected(now))
+            .collect();
+        if order.is_empty() {
+            order = (0..bases.len()).collect();
+            order.sort_by_key(|&index| stats_of(index).ejected_until);
+            return order;
+        }
+
+        order.sort_by(|&a, &b| {
+            let (a, b) = (stats_of(a), stats_of(b));
+            a.consecutive_failures.cmp(&b.consecutive_failures).then(
+                a.latency_ms
+                    .unwrap_or(UNKNOWN_LATENCY_MS)
+                    .total_cmp(&b.latency_ms.unwrap_or(UNKNOWN_LATENCY_MS)),
+            )
+        });
+        order
+    }
+
+    pub fn is_ejected(&self, base: &str) -> bool {
+        self.lock()
+            .get(base)
+            .is_some_and(|stats| stats.is_ejected(unix_now()))
+    }
+
+    pub fn record_success(&self, base: &str, latency: Duration) {
+        let mut stats = self.lock();
+        let entry = stats.entry(base.to_string()).or_default();
+        let sample = latency.as_secs_f64() * 1_000.0;
+        entry.latency_ms = Some(match entry.latency_ms {
+            Some(average) => average + LATENCY_WEIGHT * (sample - average),
+            None => sample,
+        });
+        entry.successes += 1;
+        entry.consecutive_failures = 0;
+        entry.ejected_until = None;
+    }
+
+    pub fn record_failure(&self, base: &str) {
+        let mut stats = self.lock();
+        let entry = stats.entry(base.to_string()).or_default();
+        entry.failures += 1;
+        entry.consecutive_failures += 1;
+
+        if entry.consecutive_failures >= FAILURES_BEFORE_EJECT {
+            let doublings = (entry.consecutive_failures - FAILURES_BEFORE_EJECT).min(5);
+            let eject = (FAILURE_EJECT * 2u32.pow(doublings)).min(MAX_EJECT);
+            eject_base(base, entry, eject, "repeated failures");
+        }
+    }
+
+    /// A 429 means the instance wants a break, so it is ejected right away.
+    pub fn record_rate_limited(&self, base: &str) {
+        let mut stats = self.lock();
+        let entry = stats.entry(base.to_string()).or_default();
+        entry.rate_limited += 1;
+        entry.consecutive_failures += 1;
+        eject_base(base, entry, RATE_LIMIT_EJECT, "rate limited");
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, BaseStats>> {
+        self.stats
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn eject_base(base: &str, stats: &mut BaseStats, duration: Duration, reason: &str) {
+    let until = unix_now() + duration.as_secs();
+    if stats.ejected_until.is_none_or(|current| current < until) {
+        eprintln!(
+            "Translation base {base} {reason}; skipping it for {}s.",
+            duration.as_secs()
+        );
+        stats.ejected_until = Some(until);
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+fn write_atomic(path: &Path, contents: &str) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // A temp file per write, so concurrent saves never rename each other's
+    // half-written file into place.
+    static WRITES: AtomicU64 = AtomicU64::new(0);
+    let temp = path.with_extension(format!(
+        "{}-{}.tmp",
+        process::id(),
+        WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&temp, contents)
+        .and_then(|()| fs::rename(&temp, path))
+        .inspect_err(|_| {
+            let _ = fs::remove_file(&temp);
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orders_by_failures_then_latency_and_skips_ejected() {
+        let health = BaseHealth::default();
+        health.record_success("slow", Duration::from_millis(900));
+        health.record_success("fast", Duration::from_millis(100));
+        health.record_failure("flaky");
+        health.record_rate_limited("limited");
+
+        assert_eq!(
+            health.order(&["flaky", "unknown", "slow", "limited", "fast"]),
+            vec![4, 2, 1, 0]
+        );
+        assert!(health.is_ejected("limited"));
+
+        // With nothing healthy left, ejected bases are still tried.
+        assert_eq!(health.order(&["limited"]), vec![0]);
+    }
+
+    #[test]
+    fn ejects_after_repeated_failures_and_persists() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache").join(HEALTH_FILE);
+
+        let health = BaseHealth::load(path.clone());
+        for _ in 0..FAILURES_BEFORE_EJECT {
+            assert!(!health.is_ejected("dead"));
+            health.record_failure("dead");
+        }
+        assert!(health.is_ejected("dead"));
+        health.save().unwrap();
+        health.save().unwrap();
+        let files = fs::read_dir(path.parent().unwrap()).unwrap().count();
+        assert_eq!(files, 1, "temp files are renamed into place");
+
+        let reloaded = BaseHealth::load(path);
+        assert!(reloaded.is_ejected("dead"));
+        reloaded.record_success("dead", Duration::from_millis(50));
+        assert!(!reloaded.is_ejected("dead"));
+    }
+}
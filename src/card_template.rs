@@ -1,3 +1,6 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CardFields {
     pub front: String,
     pub back: String,
@@ -8,10 +11,11 @@ pub trait CardTemplate {
     fn render(&self) -> CardFields;
 }
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct ExampleSentence {
     pub sentence: String,
     pub highlight: String,
+    pub translation: String,
 }
 
 impl ExampleSentence {
@@ -31,17 +35,223 @@ impl ExampleSentence {
             self.sentence.clone()
         }
     }
+
+    /// Renders the sentence with the highlighted span turned into a cloze
+    /// deletion, or `None` when there is nothing to blank out.
+    fn render_cloze(&self, hint: &str) -> Option<String> {
+        if self.highlight.is_empty() || !self.sentence.contains(&self.highlight) {
+            return None;
+        }
+
+        Some(
+            self.sentence
+                .replacen(&self.highlight, &cloze_deletion(&self.highlight, hint), 1),
+        )
+    }
+}
+
+impl ExampleSentence {
+    /// Renders the sentence with the highlighted span blanked out, or an
+    /// empty string when the sentence would give the answer away.
+    fn render_blanked(&self) -> String {
+        if self.highlight.is_empty() || !self.sentence.contains(&self.highlight) {
+            return String::new();
+        }
+
+        self.sentence.replacen(
+            &self.highlight,
+            "<span style=\"color:red;\">_____</span>",
+            1,
+        )
+    }
+}
+
+/// Renders examples as sentence/translation pairs for the back of a card.
+pub fn render_examples(examples: &[ExampleSentence]) -> String {
+    examples
+        .iter()
+        .map(|example| {
+            let translation = if example.translation.is_empty() {
+                String::new()
+            } else {
+                format!(
+                    "<br><span style=\"color:#888;\">{}</span>",
+                    example.translation
+                )
+            };
+            format!(
+                "<div style=\"margin-top:0.4em;\"><i>{}</i>{}</div>",
+                example.render(),
+                translation
+            )
+        })
+        .collect()
+}
+
+fn cloze_deletion(answer: &str, hint: &str) -> String {
+    // `:` and `}` could end the deletion or start the hint early, so both go
+    // in as HTML entities, which Anki shows as the characters themselves.
+    let escape = |text: &str| text.replace(':', "&#58;").replace('}', "&#125;");
+    let (answer, hint) = (escape(answer), escape(hint));
+    if hint.trim().is_empty() {
+        format!("{{{{c1::{answer}}}}}")
+    } else {
+        format!("{{{{c1::{answer}::{}}}}}", hint.trim())
+    }
+}
+
+/// How a card field was filled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldStatus {
+    Ok,
+    /// Filled with a stand-in, e.g. the untranslated term.
+    Fallback,
+    /// Left empty because its provider failed.
+    Missing,
+    /// Left empty although nothing failed, e.g. no example sentence exists.
+    Empty,
+}
+
+impl FieldStatus {
+    /// Whether the field counts against the card: a provider failed for it.
+    pub fn is_degraded(self) -> bool {
+        matches!(self, FieldStatus::Fallback | FieldStatus::Missing)
+    }
+}
+
+/// Where one card field came from.
+#[derive(Clone, Debug, Serialize)]
+pub struct FieldSource {
+    /// Field name; translated fields of extra target languages carry the
+    /// language, e.g. `translation.uk`.
+    pub field: String,
+    /// Services that produced the value; empty when nothing did.
+    pub providers: Vec<String>,
+    pub status: FieldStatus,
+    /// Why the field is degraded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// Translated fields for one target language.
+#[derive(Clone, Debug, Default)]
+pub struct TranslationSection {
+    pub lang: String,
+    pub heading: String,
+    pub synonyms: String,
+    pub usage: String,
+}
+
+impl TranslationSection {
+    /// Small language label shown above each section on bilingual cards.
+    fn render_label(&self, labelled: bool) -> String {
+        if labelled {
+            format!(
+                "<div style=\"font-size:0.75em; color:#888;\">{}</div>",
+                self.lang.to_uppercase()
+            )
+        } else {
+            String::new()
+        }
+    }
 }
 
+#[derive(Clone)]
 pub struct VocabularyCard {
     pub term: String,
     pub pronunciation: String,
     pub part_of_speech: String,
-    pub example: ExampleSentence,
-    pub translation_heading: String,
-    pub translation_synonyms: String,
-    pub translation_usage: String,
+    pub examples: Vec<ExampleSentence>,
+    /// One section per target language, the primary language first.
+    pub translations: Vec<TranslationSection>,
+    pub etymology: String,
+    pub frequency_rank: Option<usize>,
+    pub cefr_level: Option<String>,
     pub extra_tags: Vec<String>,
+    /// Provenance of each looked-up field.
+    pub sources: Vec<FieldSource>,
+    /// Lookups that failed while building the card.
+    pub warnings: Vec<String>,
+}
+
+impl VocabularyCard {
+    /// Lookup services that contributed to any field, in first-use order.
+    pub fn providers(&self) -> Vec<String> {
+        let mut providers: Vec<String> = Vec::new();
+        for provider in self.sources.iter().flat_map(|source| &source.providers) {
+            if !providers.contains(provider) {
+                providers.push(provider.clone());
+            }
+        }
+        providers
+    }
+
+    /// Fields that fell back or stayed empty because a provider failed.
+    pub fn degraded_fields(&self) -> Vec<&str> {
+        self.sources
+            .iter()
+            .filter(|source| source.status.is_degraded())
+            .map(|source| source.field.as_str())
+            .collect()
+    }
+
+    /// The translation in the first target language.
+    pub fn primary_translation(&self) -> TranslationSection {
+        self.translations.first().cloned().unwrap_or_default()
+    }
+
+    /// Translation headings of every target language, e.g. for the front of
+    /// a production card.
+    pub fn translation_headings(&self, separator: &str) -> String {
+        self.translations
+            .iter()
+            .map(|section| section.heading.as_str())
+            .filter(|heading| !heading.is_empty())
+            .collect::<Vec<_>>()
+            .join(separator)
+    }
+
+    /// Renders each target language's section with `render`, labelling the
+    /// sections when there is more than one.
+    pub fn render_translations(&self, render: impl Fn(&TranslationSection) -> String) -> String {
+        let labelled = self.translations.len() > 1;
+        self.translations
+            .iter()
+            .map(|section| format!("{}{}", section.render_label(labelled), render(section)))
+            .collect()
+    }
+
+    /// The best-ranked example, used where a card only has room for one.
+    pub fn primary_example(&self) -> ExampleSentence {
+        self.examples.first().cloned().unwrap_or_default()
+    }
+
+    /// Difficulty and origin details shown in small print on the back.
+    pub fn render_metadata(&self) -> String {
+        let mut badges = Vec::new();
+        if let Some(level) = &self.cefr_level {
+            badges.push(format!("CEFR {level}"));
+        }
+        if let Some(rank) = self.frequency_rank {
+            badges.push(format!("#{rank} most frequent"));
+        }
+
+        let mut html = String::new();
+        if !badges.is_empty() {
+            html.push_str(&format!(
+                "<div style=\"font-size:0.8em; color:#888;\">{}</div>",
+                badges.join(" · ")
+            ));
+        }
+        if !self.etymology.is_empty() {
+            html.push_str(&format!(
+                "<div style=\"font-size:0.8em; color:#888; margin-top:0.4em;\">{}</div>",
+                self.etymology
+            ));
+        }
+        html
+    }
 }
 
 impl CardTemplate for VocabularyCard {
@@ -61,22 +271,35 @@ impl CardTemplate for VocabularyCard {
             term = self.term,
             pronunciation = self.pronunciation,
             part_display = part_display,
-            example = self.example.render(),
+            example = self.primary_example().render(),
         );
 
+        let translations = self.render_translations(|section| {
+            format!(
+                concat!(
+                    "<div style=\"margin-bottom:0.2em;\">",
+                    "<b style=\"font-size:1.2em;\">{heading}</b>",
+                    "</div>",
+                    "<div style=\"margin-bottom:0.8em; color:#5e84c1;\">",
+                    "{synonyms}</div>",
+                    "<div style=\"margin-bottom:1em; font-size:0.95em; ",
+                    "line-height:1.5em; color:#ccc;\">{usage}</div>",
+                ),
+                heading = section.heading,
+                synonyms = section.synonyms,
+                usage = section.usage,
+            )
+        });
+
         let back = format!(
             concat!(
-                "<div style=\"margin-bottom:0.2em;\">",
-                "<b style=\"font-size:1.2em;\">{heading}</b>",
-                "</div>",
-                "<div style=\"margin-bottom:0.8em; color:#5e84c1;\">",
-                "{synonyms}</div>",
-                "<div style=\"margin-bottom:1em; font-size:0.95em; ",
-                "line-height:1.5em; color:#ccc;\">{usage}</div>",
+                "{translations}",
+                "<div style=\"font-size:0.9em;\">{examples}</div>",
+                "<div style=\"margin-top:0.8em;\">{metadata}</div>",
             ),
-            heading = self.translation_heading,
-            synonyms = self.translation_synonyms,
-            usage = self.translation_usage,
+            translations = translations,
+            examples = render_examples(&self.examples),
+            metadata = self.render_metadata(),
         );
 
         let mut tags = Vec::new();
@@ -105,6 +328,82 @@ impl CardTemplate for SimpleCard {
     }
 }
 
+pub struct ClozeCard {
+    pub term: String,
+    pub example: ExampleSentence,
+    pub hint: String,
+    pub translation: String,
+    pub definition: String,
+    pub tags: Vec<String>,
+}
+
+impl CardTemplate for ClozeCard {
+    /// `front` maps to the Cloze note's `Text` field, `back` to `Back Extra`.
+    fn render(&self) -> CardFields {
+        let front = self
+            .example
+            .render_cloze(&self.hint)
+            .unwrap_or_else(|| cloze_deletion(&self.term, &self.hint));
+
+        let back = format!(
+            concat!(
+                "<div style=\"font-size:1.2em;\"><b>{translation}</b></div>",
+                "<div style=\"margin-top:0.8em; color:#666;\">{definition}</div>",
+            ),
+            translation = self.translation,
+            definition = self.definition,
+        );
+
+        let mut tags = self.tags.clone();
+        if !tags.iter().any(|tag| tag == "cloze") {
+            tags.push("cloze".to_string());
+        }
+
+        CardFields { front, back, tags }
+    }
+}
+
+/// Production card: the learner sees the translation and must recall the term.
+pub struct ProductionCard {
+    pub term: String,
+    pub pronunciation: String,
+    pub translation: String,
+    pub example: ExampleSentence,
+    pub tags: Vec<String>,
+}
+
+impl CardTemplate for ProductionCard {
+    fn render(&self) -> CardFields {
+        let blanked = self.example.render_blanked();
+        let example_block = if blanked.is_empty() {
+            String::new()
+        } else {
+            format!("<br><br><i>{blanked}</i>")
+        };
+
+        let front = format!(
+            "<b style=\"font-size:1.4em;\">{translation}</b>{example_block}",
+            translation = self.translation,
+        );
+
+        let back = format!(
+            concat!(
+                "<b style=\"font-size:1.4em;\">{term}</b>",
+                "<br><span style=\"color:#888;\">{pronunciation}</span>",
+            ),
+            term = self.term,
+            pronunciation = self.pronunciation,
+        );
+
+        let mut tags = self.tags.clone();
+        if !tags.iter().any(|tag| tag == "production") {
+            tags.push("production".to_string());
+        }
+
+        CardFields { front, back, tags }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -114,6 +413,7 @@ mod tests {
         let example = ExampleSentence {
             sentence: "I was taken aback by her sudden outburst.".to_string(),
             highlight: "taken aback".to_string(),
+            translation: String::new(),
         };
 
         let rendered = example.render();
@@ -127,23 +427,157 @@ mod tests {
             term: "aback".to_string(),
             pronunciation: "/əˈbæk/".to_string(),
             part_of_speech: "adverb".to_string(),
-            example: ExampleSentence {
-                sentence: "I was taken aback by her sudden outburst.".to_string(),
-                highlight: "taken aback".to_string(),
-            },
-            translation_heading: "застигнутый врасплох".to_string(),
-            translation_synonyms: "удивлённый".to_string(),
-            translation_usage: "Используется при внезапном удивлении.".to_string(),
+            examples: vec![
+                ExampleSentence {
+                    sentence: "I was taken aback by her sudden outburst.".to_string(),
+                    highlight: "taken aback".to_string(),
+                    translation: "Я был ошеломлён её внезапной вспышкой.".to_string(),
+                },
+                ExampleSentence {
+                    sentence: "The news took him aback.".to_string(),
+                    highlight: "aback".to_string(),
+                    translation: String::new(),
+                },
+            ],
+            translations: vec![TranslationSection {
+                lang: "ru".to_string(),
+                heading: "застигнутый врасплох".to_string(),
+                synonyms: "удивлённый".to_string(),
+                usage: "Используется при внезапном удивлении.".to_string(),
+            }],
+            etymology: "Old English on bæc, 'backwards'.".to_string(),
+            frequency_rank: Some(14_250),
+            cefr_level: Some("C1".to_string()),
             extra_tags: vec!["english".to_string(), "emotion".to_string()],
+            sources: vec![
+                FieldSource {
+                    field: "translation".to_string(),
+                    providers: vec!["lingva".to_string()],
+                    status: FieldStatus::Ok,
+                    note: None,
+                },
+                FieldSource {
+                    field: "definition".to_string(),
+                    providers: vec!["dictionaryapi.dev".to_string(), "lingva".to_string()],
+                    status: FieldStatus::Fallback,
+                    note: Some("definition left untranslated".to_string()),
+                },
+            ],
+            warnings: Vec::new(),
         };
 
+        assert_eq!(card.providers(), vec!["lingva", "dictionaryapi.dev"]);
+        assert_eq!(card.degraded_fields(), vec!["definition"]);
+
         let fields = card.render();
         assert!(fields.front.contains("aback"));
         assert!(fields.back.contains("застигнутый"));
+        assert!(!fields.back.contains(">RU<"));
+        assert!(fields.back.contains("Я был ошеломлён"));
+        assert!(fields.back.contains("took him"));
+        assert!(fields.back.contains("CEFR C1 · #14250 most frequent"));
+        assert!(fields.back.contains("Old English"));
         assert!(fields.tags.contains(&"adverb".to_string()));
         assert!(fields.tags.contains(&"english".to_string()));
     }
 
+    #[test]
+    fn labels_sections_of_bilingual_cards() {
+        let section = |lang: &str, heading: &str| TranslationSection {
+            lang: lang.to_string(),
+            heading: heading.to_string(),
+            ..TranslationSection::default()
+        };
+        let card = VocabularyCard {
+            term: "aback".to_string(),
+            pronunciation: String::new(),
+            part_of_speech: String::new(),
+            examples: Vec::new(),
+            translations: vec![section("ru", "врасплох"), section("uk", "зненацька")],
+            etymology: String::new(),
+            frequency_rank: None,
+            cefr_level: None,
+            extra_tags: Vec::new(),
+            sources: Vec::new(),
+            warnings: Vec::new(),
+        };
+
+        let back = card.render().back;
+        let ru = back.find(">RU<").unwrap();
+        let uk = back.find(">UK<").unwrap();
+        assert!(ru < back.find("врасплох").unwrap());
+        assert!(uk > back.find("врасплох").unwrap());
+        assert!(uk < back.find("зненацька").unwrap());
+        assert_eq!(card.translation_headings(" / "), "врасплох / зненацька");
+    }
+
+    #[test]
+    fn renders_cloze_card_from_highlight() {
+        let card = ClozeCard {
+            term: "aback".to_string(),
+            example: ExampleSentence {
+                sentence: "I was taken aback by her sudden outburst.".to_string(),
+                highlight: "taken aback".to_string(),
+                translation: String::new(),
+            },
+            hint: "врасплох".to_string(),
+            translation: "застигнутый врасплох".to_string(),
+            definition: "Surprised or shocked.".to_string(),
+            tags: vec!["english".to_string()],
+        };
+
+        let fields = card.render();
+        assert_eq!(
+            fields.front,
+            "I was {{c1::taken aback::врасплох}} by her sudden outburst."
+        );
+        assert!(fields.back.contains("застигнутый врасплох"));
+        assert!(fields.back.contains("Surprised or shocked."));
+        assert!(fields.tags.contains(&"cloze".to_string()));
+    }
+
+    #[test]
+    fn cloze_card_falls_back_to_term_without_highlight() {
+        let card = ClozeCard {
+            term: "aback".to_string(),
+            example: ExampleSentence::default(),
+            hint: String::new(),
+            translation: String::new(),
+            definition: String::new(),
+            tags: Vec::new(),
+        };
+
+        assert_eq!(card.render().front, "{{c1::aback}}");
+        assert_eq!(
+            cloze_deletion("std::vec}}", "a::b"),
+            "{{c1::std&#58;&#58;vec&#125;&#125;::a&#58;&#58;b}}"
+        );
+        assert_eq!(cloze_deletion("{x}", ""), "{{c1::{x&#125;}}");
+        assert_eq!(cloze_deletion("ratio:", "x"), "{{c1::ratio&#58;::x}}");
+    }
+
+    #[test]
+    fn renders_production_card_with_blanked_example() {
+        let card = ProductionCard {
+            term: "aback".to_string(),
+            pronunciation: "/əˈbæk/".to_string(),
+            translation: "застигнутый врасплох".to_string(),
+            example: ExampleSentence {
+                sentence: "I was taken aback by her sudden outburst.".to_string(),
+                highlight: "taken aback".to_string(),
+                translation: String::new(),
+            },
+            tags: vec!["english".to_string()],
+        };
+
+        let fields = card.render();
+        assert!(fields.front.contains("застигнутый врасплох"));
+        assert!(fields.front.contains("_____"));
+        assert!(!fields.front.contains("taken aback"));
+        assert!(fields.back.contains("aback"));
+        assert!(fields.tags.contains(&"production".to_string()));
+    }
+
     #[test]
     fn renders_simple_card() {
         let card = SimpleCard {
@@ -0,0 +1,196 @@
+use std::{
+    env,
+    process::Stdio,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result, anyhow};
+use tokio::{process::Command, time::timeout};
+
+/// How long the clipboard tool or `notify-send` may take before it is killed,
+/// so a hung one can't stall the watcher.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Reads the system clipboard through an external command.
+pub struct Clipboard {
+    program: String,
+    args: Vec<String>,
+}
+
+impl Clipboard {
+    /// Uses `command` when given, otherwise `wl-paste` on Wayland and `xclip`
+    /// on X11.
+    pub fn new(command: Option<&str>) -> Result<Self> {
+        let command = match command {
+            Some(command) => command.to_string(),
+            None if env::var_os("WAYLAND_DISPLAY").is_some() => {
+                "wl-paste --no-newline --type text".to_string()
+            }
+            None if env::var_os("DISPLAY").is_some() => "xclip -selection clipboard -o".to_string(),
+            None => {
+                return Err(anyhow!(
+                    "No Wayland or X11 display found; set `clipboard_command` in the config"
+                ));
+            }
+        };
+
+        let mut parts = command.split_whitespace().map(str::to_string);
+        let program = parts
+            .next()
+            .ok_or_else(|| anyhow!("Clipboard command is empty"))?;
+        Ok(Self {
+            program,
+            args: parts.collect(),
+        })
+    }
+
+    /// Current clipboard text; `None` when it is empty or not text.
+    pub async fn read(&self) -> Result<Option<String>> {
+        let output = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .output();
+        let output = timeout(COMMAND_TIMEOUT, output)
+            .await
+            .map_err(|_| anyhow!("'{}' did not finish in time", self.program))?
+            .with_context(|| format!("failed to run '{}'", self.program))?;
+
+        // Both tools exit non-zero for an empty clipboard or non-text content.
+        if !output.status.success() {
+            return Ok(None);
+        }
+        Ok(String::from_utf8(output.stdout).ok())
+    }
+}
+
+/// Reports clipboard content once it has stayed the same for a while, so
+/// selections made in several steps don't each become a card.
+pub struct Debouncer {
+    delay: Duration,
+    current: Option<(String, Instant)>,
+    last_reported: Option<String>,
+}
+
+impl Debouncer {
+    pub fn new(delay: Duration) -> Self {
+        Self {
+            delay,
+            current: None,
+            last_reported: None,
+        }
+    }
+
+    /// Treats `value` as already reported.
+    pub fn skip(&mut self, value: Option<String>) {
+        self.last_reported = value;
+    }
+
+    pub fn observe(&mut self, value: Option<String>, now: Instant) -> Option<String> {
+        let value = value?;
+        match &self.current {
+            Some((current, _)) if *current == value => {}
+            _ => {
+                self.current = Some((value, now));
+                return None;
+            }
+        }
+
+        let (current, since) = self.current.as_ref()?;
+        if now.duration_since(*since) < self.delay || self.last_reported.as_ref() == Some(current) {
+            return None;
+        }
+
+        self.last_reported = Some(current.clone());
+        Some(current.clone())
+    }
+}
+
+/// Turns copied text into a term, ignoring anything that isn't a short
+/// phrase of words (URLs, code, paragraphs, numbers).
+pub fn extract_term(text: &str, max_words: usize) -> Option<String> {
+    let text = text.trim();
+    if text.is_empty() || text.contains('\n') {
+        return None;
+    }
+
+    let words: Vec<&str> = text
+        .split_whitespace()
+        .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()))
+        .filter(|word| !word.is_empty())
+        .collect();
+    if words.is_empty() || words.len() > max_words {
+        return None;
+    }
+
+    let is_word = |word: &&str| {
+        word.chars().any(char::is_alphabetic)
+            && word
+                .chars()
+                .all(|c| c.is_alphabetic() || matches!(c, '-' | '\'' | '’'))
+    };
+    if !words.iter().all(is_word) {
+        return None;
+    }
+
+    Some(words.join(" "))
+}
+
+/// Shows a desktop notification when `notify-send` is available.
+pub async fn notify(summary: &str, body: &str) {
+    let status = Command::new("notify-send")
+        .args(["--app-name=notaforge", summary, body])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .status();
+    let _ = timeout(COMMAND_TIMEOUT, status).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_short_word_phrases_only() {
+        assert_eq!(extract_term("  aback, ", 3).as_deref(), Some("aback"));
+        assert_eq!(
+            extract_term("“taken aback”", 3).as_deref(),
+            Some("taken aback")
+        );
+        assert_eq!(extract_term("well-known", 3).as_deref(), Some("well-known"));
+        assert_eq!(extract_term("https://example.com/page", 3), None);
+        assert_eq!(extract_term("let x = 42;", 3), None);
+        assert_eq!(extract_term("one two three four", 3), None);
+        assert_eq!(extract_term("line\nbreak", 3), None);
+    }
+
+    #[tokio::test]
+    async fn reads_through_the_command_and_gives_up_on_hung_ones() {
+        let echo = Clipboard::new(Some("printf aback")).unwrap();
+        assert_eq!(echo.read().await.unwrap().as_deref(), Some("aback"));
+
+        let hung = Clipboard::new(Some("sleep 30")).unwrap();
+        let err = hung.read().await.unwrap_err();
+        assert!(err.to_string().contains("did not finish in time"));
+    }
+
+    #[test]
+    fn debouncer_waits_for_stable_new_content() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut debouncer = Debouncer::new(Duration::from_millis(500));
+
+        assert_eq!(debouncer.observe(Some("ab".into()), at(0)), None);
+        assert_eq!(debouncer.observe(Some("aback".into()), at(200)), None);
+        assert_eq!(debouncer.observe(Some("aback".into()), at(500)), None);
+        assert_eq!(
+            debouncer.observe(Some("aback".into()), at(700)).as_deref(),
+            Some("aback")
+        );
+        assert_eq!(debouncer.observe(Some("aback".into()), at(1_500)), None);
+        assert_eq!(debouncer.observe(None, at(1_600)), None);
+    }
+}
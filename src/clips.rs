@@ -0,0 +1,190 @@
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+use anyhow::{Context, Result, anyhow};
+use tokio::process::Command;
+
+use crate::subtitles::Cue;
+
+pub const DEFAULT_PADDING_MS: u64 = 250;
+
+/// Cuts media for a subtitle line out of a local video with ffmpeg.
+pub struct ClipExtractor {
+    pub ffmpeg: PathBuf,
+    /// Extra audio kept before and after the cue, since subtitle timings are tight.
+    pub padding: Duration,
+}
+
+/// Audio and a still frame for one subtitle line.
+pub struct Clip {
+    /// MP3 audio of the line.
+    pub audio: Vec<u8>,
+    /// JPEG frame from the middle of the line.
+    pub screenshot: Vec<u8>,
+    pub start: Duration,
+}
+
+impl Default for ClipExtractor {
+    fn default() -> Self {
+        Self {
+            ffmpeg: PathBuf::from("ffmpeg"),
+            padding: Duration::from_millis(DEFAULT_PADDING_MS),
+        }
+    }
+}
+
+impl ClipExtractor {
+    pub async fn extract(&self, video: &Path, cue: &Cue) -> Result<Clip> {
+        if !video.is_file() {
+            return Err(anyhow!("Video '{}' not found", video.display()));
+        }
+
+        let start = cue.start.saturating_sub(self.padding);
+        let end = cue.end + self.padding;
+        let middle = cue.start + (cue.end.saturating_sub(cue.start)) / 2;
+
+        let audio = self
+            .run(audio_args(video, start, end))
+            .await
+            .context("failed to cut audio clip")?;
+        let screenshot = self
+            .run(screenshot_args(video, middle))
+            .await
+            .context("failed to take screenshot")?;
+
+        Ok(Clip {
+            audio,
+            screenshot,
+            start: cue.start,
+        })
+    }
+
+    /// Runs ffmpeg and returns what it wrote to stdout.
+    async fn run(&self, args: Vec<OsString>) -> Result<Vec<u8>> {
+        let output = Command::new(&self.ffmpeg)
+            .args(args)
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .output()
+            .await
+            .with_context(|| format!("failed to run '{}'", self.ffmpeg.display()))?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let reason = stderr.lines().last().unwrap_or("no output").trim();
+            return Err(anyhow!("ffmpeg exited with {}: {reason}", output.status));
+        }
+        if output.stdout.is_empty() {
+            return Err(anyhow!("ffmpeg produced no output"));
+        }
+
+        Ok(output.stdout)
+    }
+}
+
+fn audio_args(video: &Path, start: Duration, end: Duration) -> Vec<OsString> {
+    let mut args = base_args(video, start);
+    args.extend(
+        [
+            "-t",
+            &seconds(end.saturating_sub(start)),
+            "-vn",
+            "-ac",
+            "2",
+            "-c:a",
+            "libmp3lame",
+            "-q:a",
+            "4",
+            "-f",
+            "mp3",
+            "pipe:1",
+        ]
+        .map(OsString::from),
+    );
+    args
+}
+
+fn screenshot_args(video: &Path, at: Duration) -> Vec<OsString> {
+    let mut args = base_args(video, at);
+    args.extend(
+        [
+            "-frames:v",
+            "1",
+            "-vf",
+            "scale=640:-2",
+            "-q:v",
+            "4",
+            "-f",
+            "image2",
+            "-c:v",
+            "mjpeg",
+            "pipe:1",
+        ]
+        .map(OsString::from),
+    );
+    args
+}
+
+/// Seeking before `-i` is fast and frame-accurate with current ffmpeg.
+fn base_args(video: &Path, seek: Duration) -> Vec<OsString> {
+    let mut args: Vec<OsString> = [
+        "-hide_banner",
+        "-loglevel",
+        "error",
+        "-ss",
+        &seconds(seek),
+        "-i",
+    ]
+    .map(OsString::from)
+    .into();
+    args.push(video.as_os_str().to_owned());
+    args
+}
+
+fn seconds(duration: Duration) -> String {
+    format!("{}.{:03}", duration.as_secs(), duration.subsec_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_audio_command_for_padded_cue() {
+        let args = audio_args(
+            Path::new("show.mkv"),
+            Duration::from_millis(61_750),
+            Duration::from_millis(64_500),
+        );
+        let args: Vec<_> = args.iter().map(|arg| arg.to_string_lossy()).collect();
+
+        assert_eq!(args[3..7], ["-ss", "61.750", "-i", "show.mkv"]);
+        assert_eq!(args[7..9], ["-t", "2.750"]);
+        assert_eq!(args.last().unwrap(), "pipe:1");
+    }
+
+    #[tokio::test]
+    async fn missing_video_fails_before_running_ffmpeg() {
+        let extractor = ClipExtractor {
+            ffmpeg: PathBuf::from("/nonexistent/ffmpeg"),
+            ..ClipExtractor::default()
+        };
+        let cue = Cue {
+            index: 1,
+            start: Duration::from_secs(1),
+            end: Duration::from_secs(2),
+            text: "Hi.".to_string(),
+        };
+
+        let err = extractor
+            .extract(Path::new("/nonexistent/show.mkv"), &cue)
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("not found"));
+    }
+}
@@ -1,15 +1,30 @@
-use std::{fs, path::Path};
+use std::{
+    collections::BTreeMap,
+    env, fmt, fs,
+    path::{Path, PathBuf},
+};
 
-use anyhow::{Context, Result};
-use serde::Deserialize;
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Deserializer};
+
+use crate::anki::{DEFAULT_TIMEOUT_MS, DEFAULT_URL};
+use crate::clips::DEFAULT_PADDING_MS;
+use crate::translation::{DEFAULT_CONCURRENCY, DEFAULT_REQUESTS_PER_SECOND};
+use crate::vocab_service::DEFAULT_MAX_EXAMPLES;
 
 #[derive(Debug, Default, Deserialize)]
 pub struct AppConfig {
+    pub anki_url: Option<String>,
+    pub anki_api_key: Option<String>,
+    pub anki_timeout_ms: Option<u64>,
     pub deck: Option<String>,
     pub model: Option<String>,
     pub template: Option<String>,
+    pub cloze_model: Option<String>,
     pub source_lang: Option<String>,
-    pub target_lang: Option<String>,
+    /// One language or a list of them, e.g. `target_lang = ["ru", "uk"]`.
+    #[serde(default, deserialize_with = "string_or_list")]
+    pub target_lang: Vec<String>,
     #[serde(default)]
     pub extra_tags: Vec<String>,
     #[serde(default)]
@@ -18,32 +33,384 @@ pub struct AppConfig {
     pub legacy_translation_base: Option<String>,
     pub translate_retries: Option<u32>,
     pub translate_backoff_ms: Option<u64>,
+    pub translate_concurrency: Option<usize>,
+    pub translate_requests_per_second: Option<f64>,
+    pub reverse: Option<bool>,
+    pub duplicate_scope: Option<String>,
+    pub duplicate_first_field: Option<bool>,
+    pub max_examples: Option<usize>,
+    pub image_endpoint: Option<String>,
+    pub image_json_pointer: Option<String>,
+    pub image_dir: Option<PathBuf>,
+    pub image_field: Option<String>,
+    pub audio_field: Option<String>,
+    pub ffmpeg: Option<PathBuf>,
+    pub clip_padding_ms: Option<u64>,
+    pub clipboard_command: Option<String>,
+    pub proxy_term_field: Option<String>,
+    pub proxy_enrich_field: Option<String>,
+    pub strict: Option<bool>,
+    pub frequency_list: Option<PathBuf>,
+    pub cefr_list: Option<PathBuf>,
+    pub known_words_file: Option<PathBuf>,
+    #[serde(default)]
+    pub known_decks: Vec<String>,
+    pub known_field: Option<String>,
+    pub skip_frequency_rank: Option<usize>,
+    /// Named language-pair setups selected with `--profile`; the flat keys
+    /// above act as the default profile.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// Settings a `[profiles.<name>]` table may override.
+#[derive(Debug, Default, Deserialize)]
+pub struct Profile {
+    pub deck: Option<String>,
+    pub model: Option<String>,
+    pub template: Option<String>,
+    pub cloze_model: Option<String>,
+    pub source_lang: Option<String>,
+    #[serde(default, deserialize_with = "string_or_list")]
+    pub target_lang: Vec<String>,
+    pub translation_bases: Option<Vec<String>>,
+    pub extra_tags: Option<Vec<String>>,
+}
+
+/// How a `NOTAFORGE_*` variable's text is read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Text,
+    Integer,
+    Float,
+    Bool,
+    /// Text that `config show` masks.
+    Secret,
+    /// Comma separated, e.g. `NOTAFORGE_TARGET_LANG=ru,uk`.
+    List,
+}
+
+/// Every setting in the order `config show` prints them. Each one can also be
+/// set through the environment as `NOTAFORGE_<KEY>`.
+pub const KEYS: &[(&str, Kind)] = &[
+    ("anki_url", Kind::Text),
+    ("anki_api_key", Kind::Secret),
+    ("anki_timeout_ms", Kind::Integer),
+    ("deck", Kind::Text),
+    ("model", Kind::Text),
+    ("template", Kind::Text),
+    ("cloze_model", Kind::Text),
+    ("source_lang", Kind::Text),
+    ("target_lang", Kind::List),
+    ("extra_tags", Kind::List),
+    ("translation_bases", Kind::List),
+    ("translate_retries", Kind::Integer),
+    ("translate_backoff_ms", Kind::Integer),
+    ("translate_concurrency", Kind::Integer),
+    ("translate_requests_per_second", Kind::Float),
+    ("reverse", Kind::Bool),
+    ("duplicate_scope", Kind::Text),
+    ("duplicate_first_field", Kind::Bool),
+    ("max_examples", Kind::Integer),
+    ("strict", Kind::Bool),
+    ("image_endpoint", Kind::Text),
+    ("image_json_pointer", Kind::Text),
+    ("image_dir", Kind::Text),
+    ("image_field", Kind::Text),
+    ("audio_field", Kind::Text),
+    ("ffmpeg", Kind::Text),
+    ("clip_padding_ms", Kind::Integer),
+    ("clipboard_command", Kind::Text),
+    ("proxy_term_field", Kind::Text),
+    ("proxy_enrich_field", Kind::Text),
+    ("frequency_list", Kind::Text),
+    ("cefr_list", Kind::Text),
+    ("known_words_file", Kind::Text),
+    ("known_decks", Kind::List),
+    ("known_field", Kind::Text),
+    ("skip_frequency_rank", Kind::Integer),
+];
+
+/// Built-in values, the bottom layer.
+fn defaults() -> Vec<(&'static str, toml::Value)> {
+    vec![
+        ("anki_url", DEFAULT_URL.into()),
+        ("anki_timeout_ms", (DEFAULT_TIMEOUT_MS as i64).into()),
+        ("template", "vocabulary".into()),
+        ("cloze_model", "Cloze".into()),
+        ("source_lang", "en".into()),
+        ("target_lang", toml::Value::Array(vec!["ru".into()])),
+        ("translate_retries", 2.into()),
+        ("translate_backoff_ms", 500.into()),
+        ("translate_concurrency", (DEFAULT_CONCURRENCY as i64).into()),
+        (
+            "translate_requests_per_second",
+            DEFAULT_REQUESTS_PER_SECOND.into(),
+        ),
+        ("reverse", false.into()),
+        ("duplicate_scope", "deck".into()),
+        ("duplicate_first_field", true.into()),
+        ("max_examples", (DEFAULT_MAX_EXAMPLES as i64).into()),
+        ("strict", false.into()),
+        ("ffmpeg", "ffmpeg".into()),
+        ("clip_padding_ms", (DEFAULT_PADDING_MS as i64).into()),
+    ]
+}
+
+/// Where an effective setting came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    File,
+    Profile(String),
+    Env(String),
+    Cli(&'static str),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => f.write_str("default"),
+            Source::File => f.write_str("config file"),
+            Source::Profile(name) => write!(f, "profile '{name}'"),
+            Source::Env(var) => write!(f, "env {var}"),
+            Source::Cli(flag) => f.write_str(flag),
+        }
+    }
+}
+
+/// A command-line value: config key, value and the flag it came from.
+pub type CliOverride = (&'static str, toml::Value, &'static str);
+
+/// The effective config, stacked as defaults < file < profile < `NOTAFORGE_*`
+/// environment < command-line flags, with the source of every value.
+pub struct LayeredConfig {
+    pub config: AppConfig,
+    values: toml::Table,
+    sources: BTreeMap<String, Source>,
+}
+
+impl LayeredConfig {
+    /// Stacks the layers; `env` is normally `std::env::vars()`.
+    pub fn load(
+        path: &Path,
+        profile: Option<&str>,
+        env: impl IntoIterator<Item = (String, String)>,
+        cli: Vec<CliOverride>,
+    ) -> Result<Self> {
+        let mut layered = Self {
+            config: AppConfig::default(),
+            values: toml::Table::new(),
+            sources: BTreeMap::new(),
+        };
+        for (key, value) in defaults() {
+            layered.set(key, value, Source::Default);
+        }
+
+        let mut file = read_table(path)?.unwrap_or_default();
+        let profiles = file.remove("profiles");
+        merge_legacy_base(&mut file);
+        for (key, value) in file {
+            layered.set(&key, value, Source::File);
+        }
+
+        if let Some(name) = profile {
+            let mut profiles = match profiles {
+                Some(toml::Value::Table(profiles)) => profiles,
+                _ => toml::Table::new(),
+            };
+            let Some(overlay) = profiles.remove(name) else {
+                return Err(missing_profile(name, &profiles));
+            };
+            let overlay: Profile = overlay
+                .try_into()
+                .with_context(|| format!("invalid profile '{name}'"))?;
+            for (key, value) in overlay.values() {
+                layered.set(key, value, Source::Profile(name.to_string()));
+            }
+        }
+
+        let env: BTreeMap<String, String> = env.into_iter().collect();
+        for &(key, kind) in KEYS {
+            let var = format!("NOTAFORGE_{}", key.to_uppercase());
+            if let Some(raw) = env.get(&var) {
+                let value = parse_env(raw, kind).with_context(|| format!("invalid {var}"))?;
+                layered.set(key, value, Source::Env(var));
+            }
+        }
+
+        for (key, value, flag) in cli {
+            layered.set(key, value, Source::Cli(flag));
+        }
+
+        layered.config = finish(layered.values.clone())
+            .with_context(|| format!("invalid configuration (file '{}')", path.display()))?;
+        Ok(layered)
+    }
+
+    pub fn source(&self, key: &str) -> Option<&Source> {
+        self.sources.get(key)
+    }
+
+    /// One `key = value  # source` line per setting; unset ones are
+    /// commented out.
+    pub fn render(&self) -> String {
+        let width = KEYS.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+        let mut out = String::new();
+        for &(key, kind) in KEYS {
+            match (self.values.get(key), self.sources.get(key)) {
+                (Some(_), Some(source)) if kind == Kind::Secret => {
+                    let line = format!("{key:width$} = \"********\"");
+                    out.push_str(&format!("{line:56} # {source}\n"));
+                }
+                (Some(value), Some(source)) => {
+                    let line = format!("{key:width$} = {value}");
+                    out.push_str(&format!("{line:56} # {source}\n"));
+                }
+                _ => out.push_str(&format!("# {key} is not set\n")),
+            }
+        }
+        out
+    }
+
+    fn set(&mut self, key: &str, value: toml::Value, source: Source) {
+        self.values.insert(key.to_string(), value);
+        self.sources.insert(key.to_string(), source);
+    }
+}
+
+/// Folds the old single `translation_base` key into `translation_bases`.
+fn merge_legacy_base(file: &mut toml::Table) {
+    let Some(toml::Value::String(base)) = file.remove("translation_base") else {
+        return;
+    };
+    let base = base.trim();
+    if base.is_empty() {
+        return;
+    }
+    let bases = file
+        .entry("translation_bases")
+        .or_insert_with(|| toml::Value::Array(Vec::new()));
+    if let toml::Value::Array(bases) = bases
+        && !bases.iter().any(|existing| existing.as_str() == Some(base))
+    {
+        bases.push(base.into());
+    }
+}
+
+fn missing_profile(name: &str, profiles: &toml::Table) -> anyhow::Error {
+    let known: Vec<&str> = profiles.keys().map(String::as_str).collect();
+    if known.is_empty() {
+        anyhow!("Profile '{name}' not found; the config defines no profiles")
+    } else {
+        anyhow!(
+            "Profile '{name}' not found; available: {}",
+            known.join(", ")
+        )
+    }
+}
+
+fn parse_env(raw: &str, kind: Kind) -> Result<toml::Value> {
+    let raw = raw.trim();
+    Ok(match kind {
+        Kind::Text | Kind::Secret => raw.into(),
+        Kind::Integer => raw
+            .parse::<i64>()
+            .map_err(|_| anyhow!("expected a whole number, got '{raw}'"))?
+            .into(),
+        Kind::Float => raw
+            .parse::<f64>()
+            .map_err(|_| anyhow!("expected a number, got '{raw}'"))?
+            .into(),
+        Kind::Bool => match raw.to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => true.into(),
+            "0" | "false" | "no" | "off" => false.into(),
+            _ => return Err(anyhow!("expected true or false, got '{raw}'")),
+        },
+        Kind::List => toml::Value::Array(
+            raw.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(Into::into)
+                .collect(),
+        ),
+    })
+}
+
+impl Profile {
+    /// The keys this profile sets, as config values.
+    fn values(self) -> Vec<(&'static str, toml::Value)> {
+        let list =
+            |values: Vec<String>| toml::Value::Array(values.into_iter().map(Into::into).collect());
+        let mut values = Vec::new();
+        let text = [
+            ("deck", self.deck),
+            ("model", self.model),
+            ("template", self.template),
+            ("cloze_model", self.cloze_model),
+            ("source_lang", self.source_lang),
+        ];
+        for (key, value) in text {
+            if let Some(value) = value {
+                values.push((key, value.into()));
+            }
+        }
+        if !self.target_lang.is_empty() {
+            values.push(("target_lang", list(self.target_lang)));
+        }
+        if let Some(bases) = self.translation_bases {
+            values.push(("translation_bases", list(bases)));
+        }
+        if let Some(tags) = self.extra_tags {
+            values.push(("extra_tags", list(tags)));
+        }
+        values
+    }
+}
+
+/// Directory for state kept between runs, such as translation base health.
+pub fn cache_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .map(|base| base.join("notaforge"))
+}
+
+/// Directory for data worth keeping, such as notes queued for Anki.
+pub fn data_dir() -> Option<PathBuf> {
+    env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .map(|base| base.join("notaforge"))
 }
 
+/// Reads the config file alone, without defaults, profiles or overrides.
 pub fn load(path: &Path) -> Result<AppConfig> {
-    if path.as_os_str().is_empty() || !path.exists() {
+    let Some(table) = read_table(path)? else {
         return Ok(AppConfig::default());
+    };
+    finish(table).with_context(|| format!("failed to parse config file '{}'", path.display()))
+}
+
+fn read_table(path: &Path) -> Result<Option<toml::Table>> {
+    if path.as_os_str().is_empty() || !path.exists() {
+        return Ok(None);
     }
 
     let raw = fs::read_to_string(path)
         .with_context(|| format!("failed to read config file '{}'", path.display()))?;
-
-    let mut config: AppConfig = toml::from_str(&raw)
+    let table = toml::from_str(&raw)
         .with_context(|| format!("failed to parse config file '{}'", path.display()))?;
+    Ok(Some(table))
+}
 
-    config.extra_tags = config
-        .extra_tags
-        .into_iter()
-        .map(|tag| tag.trim().to_string())
-        .filter(|tag| !tag.is_empty())
-        .collect();
+/// Deserializes merged values and tidies up the lists.
+fn finish(table: toml::Table) -> Result<AppConfig> {
+    let mut config: AppConfig = toml::Value::Table(table).try_into()?;
 
-    config.translation_bases = config
-        .translation_bases
-        .into_iter()
-        .map(|base| base.trim().to_string())
-        .filter(|base| !base.is_empty())
-        .collect();
+    config.extra_tags = clean_list(config.extra_tags);
+    config.target_lang = clean_list(config.target_lang);
+    config.translation_bases = clean_list(config.translation_bases);
 
     if let Some(base) = config
         .legacy_translation_base
@@ -62,6 +429,43 @@ pub fn load(path: &Path) -> Result<AppConfig> {
     Ok(config)
 }
 
+/// Keys in `raw` that no config field reads, as dotted paths such as
+/// `profiles.german.dek`.
+pub fn unknown_keys(raw: &str) -> Result<Vec<String>> {
+    let mut unknown = Vec::new();
+    let _: AppConfig = serde_ignored::deserialize(toml::Deserializer::new(raw), |path| {
+        unknown.push(path.to_string())
+    })
+    .context("failed to parse config")?;
+    Ok(unknown)
+}
+
+/// Trims entries and drops blank ones.
+fn clean_list(values: Vec<String>) -> Vec<String> {
+    values
+        .into_iter()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
+fn string_or_list<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -97,7 +501,7 @@ translate_backoff_ms = 750
         assert_eq!(config.deck.as_deref(), Some("My Deck"));
         assert_eq!(config.template.as_deref(), Some("simple"));
         assert_eq!(config.source_lang.as_deref(), Some("en"));
-        assert_eq!(config.target_lang.as_deref(), Some("es"));
+        assert_eq!(config.target_lang, vec!["es".to_string()]);
         assert_eq!(
             config.translation_bases,
             vec!["https://example.com".to_string()]
@@ -109,4 +513,123 @@ translate_backoff_ms = 750
             vec!["custom".to_string(), "spaced".to_string()]
         );
     }
+
+    #[test]
+    fn target_lang_accepts_a_list() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, r#"target_lang = ["ru", " uk ", ""]"#).unwrap();
+
+        let config = load(file.path()).unwrap();
+        assert_eq!(config.target_lang, vec!["ru".to_string(), "uk".to_string()]);
+    }
+
+    #[test]
+    fn profile_overrides_flat_keys() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(
+            file,
+            r#"
+deck = "English"
+target_lang = "ru"
+extra_tags = ["english"]
+translation_base = "https://example.com"
+max_examples = 2
+
+[profiles.german]
+deck = "Deutsch"
+source_lang = "de"
+target_lang = ["en", "ru"]
+translation_bases = ["libre:http://localhost:5000"]
+"#
+        )
+        .unwrap();
+
+        let layered = LayeredConfig::load(file.path(), Some("german"), [], Vec::new()).unwrap();
+        let config = &layered.config;
+        assert_eq!(config.deck.as_deref(), Some("Deutsch"));
+        assert_eq!(config.source_lang.as_deref(), Some("de"));
+        assert_eq!(config.target_lang, vec!["en".to_string(), "ru".to_string()]);
+        assert_eq!(
+            config.translation_bases,
+            vec!["libre:http://localhost:5000".to_string()]
+        );
+        assert_eq!(config.extra_tags, vec!["english".to_string()]);
+        assert_eq!(config.max_examples, Some(2));
+        assert_eq!(
+            layered.source("deck"),
+            Some(&Source::Profile("german".to_string()))
+        );
+        assert_eq!(layered.source("max_examples"), Some(&Source::File));
+
+        let err = LayeredConfig::load(file.path(), Some("spanish"), [], Vec::new())
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("not found"));
+    }
+
+    #[test]
+    fn env_overrides_file_and_cli_overrides_env() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(
+            file,
+            r#"
+deck = "English"
+model = "Basic"
+translate_retries = 5
+"#
+        )
+        .unwrap();
+
+        let env = [
+            ("NOTAFORGE_DECK", "Env Deck"),
+            ("NOTAFORGE_TARGET_LANG", "ru, uk"),
+            ("NOTAFORGE_STRICT", "yes"),
+            ("NOTAFORGE_ANKI_API_KEY", "secret"),
+            ("NOTAFORGE_TRANSLATE_RETRIES", "4"),
+            ("UNRELATED", "ignored"),
+        ]
+        .map(|(key, value)| (key.to_string(), value.to_string()));
+        let cli = vec![("deck", "Cli Deck".into(), "--deck")];
+
+        let layered = LayeredConfig::load(file.path(), None, env.clone(), cli).unwrap();
+        let config = &layered.config;
+        assert_eq!(config.deck.as_deref(), Some("Cli Deck"));
+        assert_eq!(config.model.as_deref(), Some("Basic"));
+        assert_eq!(config.target_lang, vec!["ru".to_string(), "uk".to_string()]);
+        assert_eq!(config.strict, Some(true));
+        assert_eq!(config.translate_retries, Some(4));
+        assert_eq!(config.max_examples, Some(DEFAULT_MAX_EXAMPLES));
+        assert_eq!(layered.source("deck"), Some(&Source::Cli("--deck")));
+        assert_eq!(
+            layered.source("translate_retries"),
+            Some(&Source::Env("NOTAFORGE_TRANSLATE_RETRIES".to_string()))
+        );
+        assert_eq!(layered.source("max_examples"), Some(&Source::Default));
+        assert!(layered.render().contains("# image_dir is not set"));
+        assert!(!layered.render().contains("secret"));
+
+        let bad = [("NOTAFORGE_MAX_EXAMPLES".to_string(), "many".to_string())];
+        let err = LayeredConfig::load(file.path(), None, bad, Vec::new())
+            .err()
+            .unwrap();
+        assert!(format!("{err:#}").contains("NOTAFORGE_MAX_EXAMPLES"));
+    }
+
+    #[test]
+    fn reports_unknown_keys_with_their_path() {
+        let raw = r#"
+deck = "English"
+translation_base_urls = ["https://example.com"]
+
+[profiles.german]
+dek = "Deutsch"
+"#;
+        assert_eq!(
+            unknown_keys(raw).unwrap(),
+            vec![
+                "translation_base_urls".to_string(),
+                "profiles.german.dek".to_string()
+            ]
+        );
+    }
 }
@@ -0,0 +1,444 @@
+use std::{
+    env, fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result, anyhow};
+use clap::ValueEnum;
+use futures::future::join_all;
+
+use crate::anki::{AnkiConnect, find_deck, find_model};
+use crate::base_health::BaseHealth;
+use crate::config::{self, AppConfig, LayeredConfig};
+use crate::duplicates::DuplicateScope;
+use crate::pipeline::TemplateKind;
+use crate::translation::{DEFAULT_BASES, Translator};
+
+/// Written by `config init`; every key is commented out so the file starts
+/// out behaving exactly like having no config at all.
+pub const DEFAULT_CONFIG: &str = r#"# notaforge configuration.
+#
+# Every key is optional and commented out; uncomment what you need.
+# Each key can also be set in the environment as NOTAFORGE_<KEY>, e.g.
+# NOTAFORGE_DECK or NOTAFORGE_TARGET_LANG=ru,uk (lists are comma separated).
+# Precedence: built-in defaults < this file < --profile < environment < flags.
+# `notaforge config show` prints the effective values and their sources.
+
+## Anki
+
+# AnkiConnect endpoint, e.g. on another machine.
+# anki_url = "http://127.0.0.1:8765"
+
+# Value of AnkiConnect's apiKey setting, if it has one. Prefer setting it
+# through NOTAFORGE_ANKI_API_KEY rather than writing it here.
+# anki_api_key = "secret"
+
+# How long to wait for each AnkiConnect request.
+# anki_timeout_ms = 15000
+
+# Deck new notes go to. Required here or via --deck.
+# deck = "English"
+
+# Note type for the vocabulary and simple templates. Required here or via --model.
+# model = "Basic"
+
+# Note type for the cloze template.
+# cloze_model = "Cloze"
+
+# Card layout: "vocabulary", "simple" or "cloze".
+# template = "vocabulary"
+
+# Also add a production card (translation -> term) for every term.
+# reverse = false
+
+# Where an existing note makes a term a duplicate: "deck" (the deck and its
+# subdecks), "deck-tree" (the deck's parent and everything under it, so
+# sibling decks count) or "collection".
+# duplicate_scope = "deck"
+
+# Also count notes made by hand or by other tools as duplicates when their
+# first field is the term, ignoring markup, case and punctuation.
+# duplicate_first_field = true
+
+# Tags added to every note.
+# extra_tags = ["notaforge"]
+
+## Languages and translation
+
+# Language of the terms you add.
+# source_lang = "en"
+
+# Language(s) to translate into; a list makes bilingual cards.
+# target_lang = "ru"
+# target_lang = ["ru", "uk"]
+
+# Translation servers, tried healthiest first. Lingva API roots by default;
+# prefix LibreTranslate servers with "libre:".
+# translation_bases = ["https://lingva.ml/api/v1", "libre:http://localhost:5000"]
+
+# Retries and base backoff for failed or rate-limited translation requests.
+# translate_retries = 2
+# translate_backoff_ms = 500
+
+# Translation requests in flight at once, and per-server request rate.
+# translate_concurrency = 4
+# translate_requests_per_second = 2.0
+
+# Example sentences per card.
+# max_examples = 3
+
+# Fail a term instead of adding an incomplete card when a lookup fails.
+# strict = false
+
+## Enrichment and filtering
+
+# Word list ordered by frequency, one word per line; adds freq tags and ranks.
+# frequency_list = "/home/me/.local/share/notaforge/frequency.txt"
+
+# "word,level" list (comma or tab separated) adding CEFR levels.
+# cefr_list = "/home/me/.local/share/notaforge/cefr.csv"
+
+# Words you already know, one per line; they are skipped.
+# known_words_file = "/home/me/.local/share/notaforge/known.txt"
+
+# Extra decks whose notes count as known words, and the field holding the term.
+# known_decks = ["Mined"]
+# known_field = "Front"
+
+# Skip words more frequent than this rank.
+# skip_frequency_rank = 2000
+
+## Images
+
+# JSON endpoint returning image URLs; {term} is replaced by the term.
+# image_endpoint = "https://example.com/search?q={term}"
+
+# JSON pointer selecting the image URL in the endpoint's response.
+# image_json_pointer = "/results/0/url"
+
+# Directory of pictures named after terms, e.g. apple.jpg.
+# image_dir = "/home/me/Pictures/notaforge"
+
+# Field the picture goes into; defaults to the back field.
+# image_field = "Back"
+
+## Subtitle clips
+
+# Field the audio clip goes into; defaults to the back field.
+# audio_field = "Back"
+
+# ffmpeg binary and padding around each subtitle line.
+# ffmpeg = "ffmpeg"
+# clip_padding_ms = 250
+
+## Clipboard watcher
+
+# Command printing the clipboard; wl-paste or xclip is used when unset.
+# clipboard_command = "wl-paste --no-newline"
+
+## AnkiConnect proxy
+
+# Field holding the term in notes from other tools, and the field to enrich.
+# proxy_term_field = "Expression"
+# proxy_enrich_field = "Back"
+
+## Profiles
+#
+# Named setups selected with --profile. A profile may set deck, model,
+# template, cloze_model, source_lang, target_lang, translation_bases and
+# extra_tags; anything else comes from the keys above.
+#
+# [profiles.german]
+# deck = "Deutsch"
+# source_lang = "de"
+# target_lang = "en"
+# extra_tags = ["german"]
+"#;
+
+/// Writes the commented default config, refusing to replace an existing file
+/// unless `force` is set.
+pub fn init(path: &Path, force: bool) -> Result<()> {
+    if path.exists() && !force {
+        return Err(anyhow!(
+            "'{}' already exists; pass --force to overwrite it",
+            path.display()
+        ));
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create '{}'", parent.display()))?;
+    }
+    fs::write(path, DEFAULT_CONFIG)
+        .with_context(|| format!("failed to write '{}'", path.display()))?;
+
+    println!("Wrote {}", path.display());
+    Ok(())
+}
+
+/// Checks the config file and prints one line per finding. Returns whether
+/// no errors were found.
+pub async fn check(path: &Path) -> Result<bool> {
+    let mut report = Report::default();
+    println!("Checking {}", path.display());
+
+    if !path.exists() {
+        report.error(format!(
+            "config file not found; run `notaforge config init` to create '{}'",
+            path.display()
+        ));
+        return Ok(report.passed());
+    }
+
+    let raw = fs::read_to_string(path)
+        .with_context(|| format!("failed to read config file '{}'", path.display()))?;
+    match config::unknown_keys(&raw) {
+        Ok(keys) => {
+            for key in keys {
+                report.error(format!("unknown key '{key}'"));
+            }
+        }
+        // A value of the wrong type is reported below for the scope it breaks.
+        Err(_) if raw.parse::<toml::Table>().is_ok() => {}
+        Err(err) => {
+            report.error(format!("{err:#}"));
+            return Ok(report.passed());
+        }
+    }
+
+    let scopes = resolve_scopes(path, &raw, &mut report);
+    if scopes.is_empty() {
+        return Ok(report.passed());
+    }
+    let mut bases = Vec::new();
+    for (position, (label, config)) in scopes.iter().enumerate() {
+        // Profiles inheriting the default template were already checked.
+        let inherited = position > 0 && config.template == scopes[0].1.template;
+        if let Some(name) = &config.template
+            && !inherited
+            && TemplateKind::from_str(name, true).is_err()
+        {
+            report.error(format!("{label}: invalid template '{name}'"));
+        }
+        if let Some(name) = &config.duplicate_scope
+            && position == 0
+            && DuplicateScope::from_str(name, true).is_err()
+        {
+            report.error(format!("invalid duplicate_scope '{name}'"));
+        }
+        for base in &config.translation_bases {
+            if !bases.contains(base) {
+                bases.push(base.clone());
+            }
+        }
+    }
+    if bases.is_empty() {
+        report.ok("no translation_bases set; checking the built-in Lingva instances".to_string());
+        bases = DEFAULT_BASES.iter().map(|base| base.to_string()).collect();
+    }
+
+    check_bases(&mut report, &bases, &scopes[0].1).await;
+    check_anki(&mut report, &scopes).await?;
+
+    Ok(report.passed())
+}
+
+/// The flat keys plus every profile applied on top of them, each with the
+/// environment layered over it as a real run would. A scope that fails to
+/// load is reported and left out; if the default one fails, none are kept.
+fn resolve_scopes(path: &Path, raw: &str, report: &mut Report) -> Vec<(String, AppConfig)> {
+    let layered = |profile: Option<&str>| {
+        LayeredConfig::load(path, profile, env::vars(), Vec::new()).map(|layers| layers.config)
+    };
+    let mut scopes = Vec::new();
+    match layered(None) {
+        Ok(config) => scopes.push(("default profile".to_string(), config)),
+        Err(err) => {
+            report.error(format!("default profile: {err:#}"));
+            return scopes;
+        }
+    }
+
+    // A profile holding a value of the wrong type fails the typed load as a
+    // whole, so the names then come from the raw table.
+    let names: Vec<String> = match config::load(path) {
+        Ok(file) => file.profiles.into_keys().collect(),
+        Err(_) => match raw
+            .parse::<toml::Table>()
+            .map(|mut table| table.remove("profiles"))
+        {
+            Ok(Some(toml::Value::Table(profiles))) => {
+                profiles.into_iter().map(|(name, _)| name).collect()
+            }
+            _ => Vec::new(),
+        },
+    };
+    for name in names {
+        let label = format!("profile '{name}'");
+        match layered(Some(&name)) {
+            Ok(config) => scopes.push((label, config)),
+            Err(err) => report.error(format!("{label}: {err:#}")),
+        }
+    }
+    scopes
+}
+
+async fn check_bases(report: &mut Report, bases: &[String], config: &AppConfig) {
+    let source = config.source_lang.as_deref().unwrap_or("en");
+    let target = config.target_lang.first().map_or("ru", String::as_str);
+    let http = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .unwrap_or_default();
+
+    let results = join_all(bases.iter().map(|base| {
+        let translator = Translator::new(
+            http.clone(),
+            std::slice::from_ref(base),
+            0,
+            0,
+            1,
+            0.0,
+            BaseHealth::default(),
+        );
+        async move {
+            let started = Instant::now();
+            let result = translator
+                .translate_batch(&["hello".to_string()], source, target)
+                .await
+                .remove(0);
+            (result, started.elapsed())
+        }
+    }))
+    .await;
+
+    for (base, (result, elapsed)) in bases.iter().zip(results) {
+        match result {
+            Ok(_) => report.ok(format!(
+                "translation base {base} answered in {} ms",
+                elapsed.as_millis()
+            )),
+            Err(err) => report.error(format!("translation base {base} is unreachable: {err:#}")),
+        }
+    }
+}
+
+async fn check_anki(report: &mut Report, scopes: &[(String, AppConfig)]) -> Result<()> {
+    let client = AnkiConnect::from_config(&scopes[0].1)?;
+    match client.check().await {
+        Ok(version) => report.ok(format!(
+            "AnkiConnect {version} answered at {}",
+            client.url()
+        )),
+        Err(err) => {
+            report.error(format!("{err:#}; decks and models were not checked"));
+            return Ok(());
+        }
+    }
+
+    for (label, config) in scopes {
+        match &config.deck {
+            Some(deck) => match find_deck(&client, deck).await {
+                Ok(_) => report.ok(format!("{label}: deck '{deck}' exists")),
+                Err(err) => report.error(format!("{label}: {err:#}")),
+            },
+            None => report.warning(format!("{label}: no deck set; --deck will be required")),
+        }
+
+        let cloze = config
+            .template
+            .as_deref()
+            .and_then(|name| TemplateKind::from_str(name, true).ok())
+            .is_some_and(|kind| matches!(kind, TemplateKind::Cloze));
+        let model = if cloze {
+            Some(config.cloze_model.as_deref().unwrap_or("Cloze"))
+        } else {
+            config.model.as_deref()
+        };
+        match model {
+            Some(model) => match find_model(&client, model).await {
+                Ok(_) => report.ok(format!("{label}: model '{model}' exists")),
+                Err(err) => report.error(format!("{label}: {err:#}")),
+            },
+            None => report.warning(format!("{label}: no model set; --model will be required")),
+        }
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct Report {
+    errors: usize,
+}
+
+impl Report {
+    fn ok(&mut self, message: String) {
+        println!("ok: {message}");
+    }
+
+    fn warning(&mut self, message: String) {
+        println!("warning: {message}");
+    }
+
+    fn error(&mut self, message: String) {
+        self.errors += 1;
+        println!("error: {message}");
+    }
+
+    fn passed(&self) -> bool {
+        if self.errors > 0 {
+            println!("{} problem(s) found", self.errors);
+        }
+        self.errors == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_config_is_valid_and_documents_every_key() {
+        assert!(config::unknown_keys(DEFAULT_CONFIG).unwrap().is_empty());
+        let parsed: AppConfig = toml::from_str(DEFAULT_CONFIG).unwrap();
+        assert!(parsed.deck.is_none());
+
+        // Uncommenting the documented keys must not produce unknown keys.
+        let uncommented: String = DEFAULT_CONFIG
+            .lines()
+            .filter_map(|line| line.strip_prefix("# "))
+            .filter(|line| line.contains(" = ") && !line.starts_with("target_lang = ["))
+            .map(|line| format!("{line}\n"))
+            .collect();
+        assert!(uncommented.contains("proxy_enrich_field"));
+        let (flat, profile) = uncommented.split_once("deck = \"Deutsch\"").unwrap();
+        let raw = format!("{flat}\n[profiles.german]\ndeck = \"Deutsch\"{profile}");
+        assert_eq!(config::unknown_keys(&raw).unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn init_refuses_to_overwrite_without_force() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notaforge").join("config.toml");
+
+        init(&path, false).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), DEFAULT_CONFIG);
+        assert!(init(&path, false).is_err());
+        init(&path, true).unwrap();
+    }
+
+    #[test]
+    fn invalid_profile_is_reported_without_hiding_the_others() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let raw = "deck = \"Mined\"\n\n[profiles.broken]\ntranslation_bases = \"x\"\n\n[profiles.german]\ndeck = \"Deutsch\"\n";
+        fs::write(&path, raw).unwrap();
+
+        let mut report = Report::default();
+        let scopes = resolve_scopes(&path, raw, &mut report);
+        assert_eq!(report.errors, 1);
+        let labels: Vec<&str> = scopes.iter().map(|(label, _)| label.as_str()).collect();
+        assert_eq!(labels, ["default profile", "profile 'german'"]);
+        assert_eq!(scopes[1].1.deck.as_deref(), Some("Deutsch"));
+    }
+}
@@ -0,0 +1,280 @@
+use std::collections::HashSet;
+
+use ankiconnect_rs::builders::{Query, QueryBuilder};
+use anyhow::Result;
+use clap::ValueEnum;
+use serde_json::{Value, json};
+
+use crate::anki::AnkiConnect;
+use crate::pipeline::CardDirection;
+
+/// Where an existing note makes a new one a duplicate.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum DuplicateScope {
+    /// The target deck and its subdecks.
+    #[default]
+    Deck,
+    /// The target deck's parent and everything under it, so sibling decks
+    /// count; a top-level deck is its own tree.
+    DeckTree,
+    /// The whole collection.
+    Collection,
+}
+
+impl DuplicateScope {
+    /// The deck to search, or `None` to search everywhere.
+    fn search_deck(self, deck: &str) -> Option<&str> {
+        match self {
+            DuplicateScope::Deck => Some(deck),
+            DuplicateScope::DeckTree => {
+                Some(deck.rsplit_once("::").map_or(deck, |(parent, _)| parent))
+            }
+            DuplicateScope::Collection => None,
+        }
+    }
+
+    /// The `options` AnkiConnect checks a new note in `deck` against, so
+    /// Anki's own duplicate check covers the same decks as ours.
+    pub fn note_options(self, deck: &str) -> Value {
+        match self.search_deck(deck) {
+            Some(deck) => json!({
+                "allowDuplicate": false,
+                "duplicateScope": "deck",
+                "duplicateScopeOptions": { "deckName": deck, "checkChildren": true },
+            }),
+            None => json!({ "allowDuplicate": false, "duplicateScope": "collection" }),
+        }
+    }
+}
+
+/// How existing notes are recognised before adding new ones.
+#[derive(Copy, Clone, Debug)]
+pub struct Duplicates {
+    pub scope: DuplicateScope,
+    /// Also treat a note not made by us as a duplicate when its normalized
+    /// first field is the term.
+    pub first_field: bool,
+}
+
+impl Default for Duplicates {
+    fn default() -> Self {
+        Self {
+            scope: DuplicateScope::Deck,
+            first_field: true,
+        }
+    }
+}
+
+impl Duplicates {
+    /// Generated notes for the term and direction within the scope.
+    pub fn query(&self, deck: &str, term_tag: &str, direction: CardDirection) -> Query {
+        let builder = QueryBuilder::new()
+            .has_tag("auto-generated")
+            .and()
+            .has_tag(term_tag)
+            .and();
+
+        // Both directions share the term tag, so the `production` tag tells them apart.
+        let tags = match direction {
+            CardDirection::Recognition => builder.not().has_tag("production"),
+            CardDirection::Production => builder.has_tag("production"),
+        }
+        .build();
+        self.scoped(deck, tags.as_str())
+    }
+
+    /// Every generated note within the scope, for fetching their term tags at once.
+    pub fn generated_query(&self, deck: &str) -> Query {
+        let generated = QueryBuilder::new().has_tag("auto-generated").build();
+        self.scoped(deck, &format!("{} tag:term:*", generated.as_str()))
+    }
+
+    /// Normalized forms of the `terms` that already have a note not made by
+    /// us within the scope, matched on the note's first field whatever its
+    /// note type. Empty when first-field matching is off.
+    pub async fn find_handmade(
+        &self,
+        client: &AnkiConnect,
+        deck: &str,
+        terms: &[&str],
+    ) -> Result<HashSet<String>> {
+        let wanted: HashSet<String> = terms.iter().map(|term| normalize_term(term)).collect();
+        if !self.first_field || wanted.iter().all(String::is_empty) {
+            return Ok(HashSet::new());
+        }
+
+        // Searching only fields that come first in some note type keeps the
+        // candidates few even across the collection; the first field is then
+        // compared exactly once markup and case are gone.
+        let fields = client.first_field_names().await?;
+        if fields.is_empty() {
+            return Ok(HashSet::new());
+        }
+        let query = self.scoped(deck, &handmade_search(&fields, terms));
+        let ids = client.find_notes(&query).await?;
+        if ids.is_empty() {
+            return Ok(HashSet::new());
+        }
+
+        Ok(client
+            .notes_info(&ids)
+            .await?
+            .iter()
+            .filter_map(|info| info.first_field())
+            .map(normalize_term)
+            .filter(|value| wanted.contains(value))
+            .collect())
+    }
+
+    /// Where duplicates were looked for, for messages.
+    pub fn describe(&self, deck: &str) -> String {
+        match (self.scope, self.scope.search_deck(deck)) {
+            (DuplicateScope::DeckTree, Some(parent)) => format!("deck tree '{parent}'"),
+            (_, Some(deck)) => format!("deck '{deck}'"),
+            (_, None) => "the collection".to_string(),
+        }
+    }
+
+    fn scoped(&self, deck: &str, query: &str) -> Query {
+        match self.scope.search_deck(deck) {
+            Some(deck) => {
+                let deck = QueryBuilder::new().in_deck(deck).build();
+                Query::custom(format!("{} {query}", deck.as_str()))
+            }
+            None => Query::custom(query.to_string()),
+        }
+    }
+}
+
+/// Notes not made by us with one of the `terms` in a field named like a
+/// first field; wildcards let markup around the term through.
+fn handmade_search(fields: &[String], terms: &[&str]) -> String {
+    let clauses: Vec<String> = fields
+        .iter()
+        .flat_map(|field| {
+            terms
+                .iter()
+                .filter(|term| !term.trim().is_empty())
+                .map(move |term| format!("\"{}:*{}*\"", escape_search(field), escape_search(term)))
+        })
+        .collect();
+    format!("-tag:auto-generated ({})", clauses.join(" OR "))
+}
+
+/// A term or field value reduced to what matters for comparing them: markup,
+/// common entities, case, repeated whitespace and surrounding punctuation
+/// are dropped.
+pub fn normalize_term(value: &str) -> String {
+    let mut text = String::with_capacity(value.len());
+    let mut in_tag = false;
+    for c in value.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#58;", ":")
+        .replace("&#125;", "}")
+        .replace("&amp;", "&");
+
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_matches(|c: char| !c.is_alphanumeric())
+        .to_lowercase()
+}
+
+/// Escapes a term for use inside a quoted Anki search.
+fn escape_search(term: &str) -> String {
+    let mut escaped = String::with_capacity(term.len());
+    for c in term.trim().chars() {
+        if matches!(c, '"' | '*' | '_' | '\\' | ':') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicate_query_matches_expected_structure() {
+        let query = Duplicates::default().query("My Deck", "term:word", CardDirection::Recognition);
+        assert_eq!(
+            query.as_str(),
+            "deck:\"My Deck\" tag:auto\\-generated tag:term\\:word -tag:production"
+        );
+    }
+
+    #[test]
+    fn production_duplicate_query_requires_production_tag() {
+        let query = Duplicates::default().query("My Deck", "term:word", CardDirection::Production);
+        assert_eq!(
+            query.as_str(),
+            "deck:\"My Deck\" tag:auto\\-generated tag:term\\:word tag:production"
+        );
+    }
+
+    #[test]
+    fn scopes_widen_the_searched_decks() {
+        let query = |scope| {
+            let duplicates = Duplicates {
+                scope,
+                first_field: true,
+            };
+            duplicates
+                .generated_query("Languages::English::Mined")
+                .as_str()
+                .to_string()
+        };
+        assert_eq!(
+            query(DuplicateScope::Deck),
+            "deck:Languages\\:\\:English\\:\\:Mined tag:auto\\-generated tag:term:*"
+        );
+        assert!(
+            query(DuplicateScope::DeckTree)
+                .starts_with("deck:Languages\\:\\:English tag:auto\\-generated")
+        );
+        assert_eq!(
+            query(DuplicateScope::Collection),
+            "tag:auto\\-generated tag:term:*"
+        );
+
+        let tree = DuplicateScope::DeckTree.note_options("Languages::English::Mined");
+        assert_eq!(
+            tree["duplicateScopeOptions"]["deckName"],
+            "Languages::English"
+        );
+        assert_eq!(DuplicateScope::DeckTree.search_deck("Mined"), Some("Mined"));
+        let collection = DuplicateScope::Collection.note_options("Mined");
+        assert_eq!(collection["duplicateScope"], "collection");
+    }
+
+    #[test]
+    fn normalizes_markup_case_and_punctuation() {
+        assert_eq!(normalize_term("<b>Taken&nbsp; Aback</b>!"), "taken aback");
+        assert_eq!(normalize_term("  Über<br>"), "über");
+        assert_eq!(normalize_term("rock &amp; roll"), "rock & roll");
+        assert_eq!(escape_search("a:b \"c\""), "a\\:b \\\"c\\\"");
+    }
+
+    #[test]
+    fn handmade_search_covers_first_fields_only() {
+        let fields = ["Expression".to_string(), "Front".to_string()];
+        assert_eq!(
+            handmade_search(&fields, &["aback", " "]),
+            "-tag:auto-generated (\"Expression:*aback*\" OR \"Front:*aback*\")"
+        );
+    }
+}
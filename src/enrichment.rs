@@ -0,0 +1,179 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::{Context, Result};
+
+use crate::card_template::VocabularyCard;
+
+const CEFR_LEVELS: &[&str] = &["A1", "A2", "B1", "B2", "C1", "C2"];
+const FREQUENCY_BUCKETS: &[(usize, &str)] = &[
+    (1_000, "top1k"),
+    (2_000, "top2k"),
+    (5_000, "top5k"),
+    (10_000, "top10k"),
+    (20_000, "top20k"),
+    (50_000, "top50k"),
+];
+
+/// Offline word metadata loaded from local lists.
+#[derive(Default)]
+pub struct Enrichment {
+    frequency: Option<HashMap<String, usize>>,
+    cefr: Option<HashMap<String, String>>,
+}
+
+impl Enrichment {
+    pub fn load(frequency_list: Option<&Path>, cefr_list: Option<&Path>) -> Result<Self> {
+        let frequency = frequency_list
+            .map(|path| read_list(path).map(|raw| parse_frequency_list(&raw)))
+            .transpose()?;
+        let cefr = cefr_list
+            .map(|path| read_list(path).map(|raw| parse_cefr_list(&raw)))
+            .transpose()?;
+
+        Ok(Self { frequency, cefr })
+    }
+
+    pub fn has_frequency_list(&self) -> bool {
+        self.frequency.is_some()
+    }
+
+    /// 1-based frequency rank of the term, if a frequency list is loaded and has it.
+    pub fn frequency_rank(&self, term: &str) -> Option<usize> {
+        self.frequency.as_ref()?.get(&normalize(term)).copied()
+    }
+
+    pub fn cefr_level(&self, term: &str) -> Option<&str> {
+        self.cefr
+            .as_ref()?
+            .get(&normalize(term))
+            .map(String::as_str)
+    }
+
+    /// Fills in the card's rank and level and adds the matching filter tags.
+    pub fn apply(&self, card: &mut VocabularyCard) {
+        card.frequency_rank = self.frequency_rank(&card.term);
+        card.cefr_level = self.cefr_level(&card.term).map(str::to_string);
+
+        if self.frequency.is_some() {
+            card.extra_tags
+                .push(format!("freq:{}", frequency_bucket(card.frequency_rank)));
+        }
+        if let Some(level) = &card.cefr_level {
+            card.extra_tags.push(format!("cefr:{level}"));
+        }
+    }
+}
+
+fn read_list(path: &Path) -> Result<String> {
+    fs::read_to_string(path).with_context(|| format!("failed to read list '{}'", path.display()))
+}
+
+fn normalize(term: &str) -> String {
+    term.trim().to_lowercase()
+}
+
+fn columns(line: &str) -> Vec<&str> {
+    line.split(['\t', ',', ';', ' '])
+        .map(|column| column.trim().trim_matches('"'))
+        .filter(|column| !column.is_empty())
+        .collect()
+}
+
+fn data_lines(raw: &str) -> impl Iterator<Item = Vec<&str>> {
+    raw.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(columns)
+        .filter(|columns| !columns.is_empty())
+}
+
+/// Parses a frequency list into ranks.
+///
+/// Accepts plain ranked word lists (one word per line, most frequent first)
+/// as well as exports with a count column such as SUBTLEX or wordfreq; when
+/// every row has a numeric second column the rows are ranked by it, since
+/// SUBTLEX files are sorted alphabetically. A header row is skipped.
+fn parse_frequency_list(raw: &str) -> HashMap<String, usize> {
+    let mut rows: Vec<(String, Option<f64>)> = data_lines(raw)
+        .map(|columns| {
+            let count = columns.get(1).and_then(|value| value.parse::<f64>().ok());
+            (normalize(columns[0]), count)
+        })
+        .collect();
+
+    if rows
+        .first()
+        .is_some_and(|(_, count)| count.is_none() && rows.len() > 1 && rows[1].1.is_some())
+    {
+        rows.remove(0);
+    }
+
+    if !rows.is_empty() && rows.iter().all(|(_, count)| count.is_some()) {
+        rows.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    }
+
+    let mut ranks = HashMap::with_capacity(rows.len());
+    for (index, (word, _)) in rows.into_iter().enumerate() {
+        ranks.entry(word).or_insert(index + 1);
+    }
+    ranks
+}
+
+/// Parses `word,level` rows; rows without a valid CEFR level (including a
+/// header) are ignored.
+fn parse_cefr_list(raw: &str) -> HashMap<String, String> {
+    data_lines(raw)
+        .filter_map(|columns| {
+            let level = columns.last()?.to_ascii_uppercase();
+            if columns.len() < 2 || !CEFR_LEVELS.contains(&level.as_str()) {
+                return None;
+            }
+            let word = columns[..columns.len() - 1].join(" ");
+            Some((normalize(&word), level))
+        })
+        .collect()
+}
+
+fn frequency_bucket(rank: Option<usize>) -> &'static str {
+    rank.and_then(|rank| {
+        FREQUENCY_BUCKETS
+            .iter()
+            .find(|(limit, _)| rank <= *limit)
+            .map(|(_, name)| *name)
+    })
+    .unwrap_or("rare")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranks_plain_and_counted_frequency_lists() {
+        let plain = parse_frequency_list("the\nof\n\n# comment\nand\n");
+        assert_eq!(plain.get("of"), Some(&2));
+        assert_eq!(plain.get("and"), Some(&3));
+
+        let subtlex = parse_frequency_list("Word\tFREQcount\naback\t12\nthe\t150000\nof\t90000\n");
+        assert_eq!(subtlex.get("the"), Some(&1));
+        assert_eq!(subtlex.get("of"), Some(&2));
+        assert_eq!(subtlex.get("aback"), Some(&3));
+        assert!(!subtlex.contains_key("word"));
+    }
+
+    #[test]
+    fn parses_cefr_table_and_skips_header() {
+        let table = parse_cefr_list("headword,CEFR\nabandon,b2\ntake aback,C1\n");
+        assert_eq!(table.get("abandon").map(String::as_str), Some("B2"));
+        assert_eq!(table.get("take aback").map(String::as_str), Some("C1"));
+        assert_eq!(table.len(), 2);
+    }
+
+    #[test]
+    fn buckets_frequency_ranks() {
+        assert_eq!(frequency_bucket(Some(1)), "top1k");
+        assert_eq!(frequency_bucket(Some(4_321)), "top5k");
+        assert_eq!(frequency_bucket(Some(70_000)), "rare");
+        assert_eq!(frequency_bucket(None), "rare");
+    }
+}
@@ -0,0 +1,75 @@
+use std::fmt;
+
+/// Exit code when nothing failed but at least one term was already in the deck.
+pub const EXIT_DUPLICATE: u8 = 6;
+
+/// Failures scripts may want to react to, each with its own exit code.
+///
+/// Attached to errors with `anyhow::Error::new(kind).context(message)`, so
+/// the message stays first in the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    AnkiUnreachable,
+    /// AnkiConnect refused to serve us (wrong API key, too old), or something
+    /// else answered at its URL.
+    AnkiRejected,
+    MissingDeckOrModel,
+    ProviderFailed,
+}
+
+impl ErrorKind {
+    pub fn exit_code(self) -> u8 {
+        match self {
+            ErrorKind::AnkiUnreachable => 3,
+            ErrorKind::MissingDeckOrModel => 4,
+            ErrorKind::ProviderFailed => 5,
+            ErrorKind::AnkiRejected => 7,
+        }
+    }
+
+    /// Stable identifier used in JSON output.
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorKind::AnkiUnreachable => "anki_unreachable",
+            ErrorKind::MissingDeckOrModel => "missing_deck_or_model",
+            ErrorKind::ProviderFailed => "provider_failed",
+            ErrorKind::AnkiRejected => "anki_rejected",
+        }
+    }
+
+    /// Finds the kind attached to `err` or one of its causes.
+    pub fn of(err: &anyhow::Error) -> Option<Self> {
+        err.chain()
+            .find_map(|cause| cause.downcast_ref::<ErrorKind>().copied())
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            ErrorKind::AnkiUnreachable => "AnkiConnect is unreachable",
+            ErrorKind::MissingDeckOrModel => "deck or model missing",
+            ErrorKind::ProviderFailed => "lookup provider failed",
+            ErrorKind::AnkiRejected => "AnkiConnect rejected the request",
+        };
+        f.write_str(text)
+    }
+}
+
+impl std::error::Error for ErrorKind {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_attached_kind_below_context() {
+        let err = anyhow::Error::new(ErrorKind::MissingDeckOrModel)
+            .context("Deck 'French' not found")
+            .context("while preparing the run");
+
+        assert_eq!(ErrorKind::of(&err), Some(ErrorKind::MissingDeckOrModel));
+        assert_eq!(format!("{err}"), "while preparing the run");
+        assert_eq!(ErrorKind::of(&anyhow::anyhow!("other")), None);
+    }
+}
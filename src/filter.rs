@@ -0,0 +1,208 @@
+use std::{
+    collections::HashSet,
+    fmt, fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use ankiconnect_rs::builders::{Query, QueryBuilder};
+use anyhow::{Context, Result};
+
+use crate::anki::AnkiConnect;
+use crate::enrichment::Enrichment;
+use crate::pipeline::build_term_tag;
+
+/// Why a term was dropped from a word list.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Exclusion {
+    KnownWordsFile(PathBuf),
+    TooFrequent { rank: usize, threshold: usize },
+    TermTag { deck: String },
+    Field { deck: String, field: String },
+}
+
+impl fmt::Display for Exclusion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Exclusion::KnownWordsFile(path) => {
+                write!(f, "listed in known-words file '{}'", path.display())
+            }
+            Exclusion::TooFrequent { rank, threshold } => {
+                write!(f, "frequency rank {rank} is within the top {threshold}")
+            }
+            Exclusion::TermTag { deck } => write!(f, "term tag found in deck '{deck}'"),
+            Exclusion::Field { deck, field } => {
+                write!(f, "{field} field matches a note in deck '{deck}'")
+            }
+        }
+    }
+}
+
+/// Drops terms the learner already knows before any lookups happen.
+///
+/// Rules are checked from cheapest to most expensive: the known-words file,
+/// the frequency threshold, then the configured Anki decks.
+pub struct KnownWordsFilter<'a> {
+    pub client: &'a AnkiConnect,
+    pub enrichment: &'a Enrichment,
+    pub known_words: Option<(PathBuf, HashSet<String>)>,
+    /// Terms ranked at or above this position in the frequency list are skipped.
+    pub max_frequency_rank: Option<usize>,
+    pub decks: Vec<String>,
+    /// Field compared against the term for notes without a `term:` tag.
+    pub field: String,
+}
+
+impl KnownWordsFilter<'_> {
+    pub async fn check(&self, term: &str) -> Result<Option<Exclusion>> {
+        if let Some((path, words)) = &self.known_words
+            && words.contains(&normalize(term))
+        {
+            return Ok(Some(Exclusion::KnownWordsFile(path.clone())));
+        }
+
+        if let (Some(threshold), Some(rank)) = (
+            self.max_frequency_rank,
+            self.enrichment.frequency_rank(term),
+        ) && rank <= threshold
+        {
+            return Ok(Some(Exclusion::TooFrequent { rank, threshold }));
+        }
+
+        let term_tag = build_term_tag(term);
+        for deck in &self.decks {
+            let by_tag = QueryBuilder::new()
+                .in_deck(deck)
+                .and()
+                .has_tag(&term_tag)
+                .build();
+            if !self.client.find_cards(&by_tag).await?.is_empty() {
+                return Ok(Some(Exclusion::TermTag { deck: deck.clone() }));
+            }
+
+            let by_field = build_field_query(deck, &self.field, term);
+            if !self.client.find_cards(&by_field).await?.is_empty() {
+                return Ok(Some(Exclusion::Field {
+                    deck: deck.clone(),
+                    field: self.field.clone(),
+                }));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the terms that passed every rule, reporting each exclusion on stderr.
+    pub async fn apply(&self, terms: Vec<String>) -> Result<Vec<String>> {
+        let mut kept = Vec::with_capacity(terms.len());
+        for term in terms {
+            match self.check(&term).await? {
+                Some(reason) => eprintln!("Skipping '{term}': {reason}."),
+                None => kept.push(term),
+            }
+        }
+        Ok(kept)
+    }
+}
+
+/// Reads a word list from a file, or from stdin when the path is `-`.
+///
+/// Blank lines and `#` comments are ignored and repeated terms are dropped.
+pub fn read_word_list(path: &Path) -> Result<Vec<String>> {
+    let raw = if path.as_os_str() == "-" {
+        let mut raw = String::new();
+        io::stdin()
+            .read_to_string(&mut raw)
+            .context("failed to read word list from stdin")?;
+        raw
+    } else {
+        fs::read_to_string(path)
+            .with_context(|| format!("failed to read word list '{}'", path.display()))?
+    };
+
+    Ok(parse_word_list(&raw))
+}
+
+pub fn load_known_words(path: &Path) -> Result<HashSet<String>> {
+    let raw = fs::read_to_string(path)
+        .with_context(|| format!("failed to read known-words file '{}'", path.display()))?;
+    Ok(parse_word_list(&raw)
+        .iter()
+        .map(|word| normalize(word))
+        .collect())
+}
+
+fn parse_word_list(raw: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    raw.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter(|line| seen.insert(normalize(line)))
+        .map(str::to_string)
+        .collect()
+}
+
+fn normalize(term: &str) -> String {
+    term.trim().to_lowercase()
+}
+
+/// Exact field match; quoted so multi-word terms stay a single search term.
+fn build_field_query(deck: &str, field: &str, value: &str) -> Query {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.trim().chars() {
+        if matches!(c, '"' | '*' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    let deck_part = QueryBuilder::new().in_deck(deck).build();
+    Query::custom(format!("{} \"{}:{}\"", deck_part.as_str(), field, escaped))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_word_list_skipping_comments_and_repeats() {
+        let words = parse_word_list("# chapter 3\naback\n\n  taken aback \nAback\n");
+        assert_eq!(words, vec!["aback".to_string(), "taken aback".to_string()]);
+    }
+
+    #[test]
+    fn field_query_quotes_multi_word_terms() {
+        let query = build_field_query("My Deck", "Front", "taken_aback \"now\"");
+        assert_eq!(
+            query.as_str(),
+            "deck:\"My Deck\" \"Front:taken\\_aback \\\"now\\\"\""
+        );
+    }
+
+    #[tokio::test]
+    async fn known_words_file_rule_runs_before_anki() {
+        let client = AnkiConnect::new(
+            "http://127.0.0.1:9",
+            None,
+            std::time::Duration::from_secs(1),
+        )
+        .unwrap();
+        let enrichment = Enrichment::default();
+        let filter = KnownWordsFilter {
+            client: &client,
+            enrichment: &enrichment,
+            known_words: Some((
+                PathBuf::from("known.txt"),
+                HashSet::from(["aback".to_string()]),
+            )),
+            max_frequency_rank: None,
+            decks: vec!["English".to_string()],
+            field: "Front".to_string(),
+        };
+
+        assert_eq!(
+            filter.check("Aback").await.unwrap(),
+            Some(Exclusion::KnownWordsFile(PathBuf::from("known.txt")))
+        );
+    }
+}
@@ -0,0 +1,196 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+use reqwest::Client;
+use serde_json::Value;
+
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "gif", "webp", "svg"];
+
+/// Where to look for a picture of the term.
+///
+/// The local directory is checked first; the endpoint is only queried when
+/// nothing matched there.
+#[derive(Debug, Default)]
+pub struct ImageLookup {
+    /// URL returning JSON with image URLs. `{term}` is replaced by the
+    /// URL-encoded term; without a placeholder the term is sent as `?q=`.
+    pub endpoint: Option<String>,
+    /// JSON pointer (e.g. `/results/0/url`) selecting the image URL. When
+    /// unset, the first http(s) string in the response is used.
+    pub json_pointer: Option<String>,
+    /// Directory holding files named after terms, e.g. `apple.jpg`.
+    pub directory: Option<PathBuf>,
+}
+
+pub struct ImageFile {
+    pub extension: String,
+    pub data: Vec<u8>,
+}
+
+impl ImageLookup {
+    pub fn is_enabled(&self) -> bool {
+        self.endpoint.is_some() || self.directory.is_some()
+    }
+
+    pub async fn find(&self, client: &Client, term: &str) -> Result<Option<ImageFile>> {
+        if let Some(path) = self
+            .directory
+            .as_deref()
+            .and_then(|dir| find_local_image(dir, term))
+        {
+            let data = tokio::fs::read(&path)
+                .await
+                .with_context(|| format!("failed to read image '{}'", path.display()))?;
+            return Ok(Some(ImageFile {
+                extension: extension_of(path.to_string_lossy().as_ref()),
+                data,
+            }));
+        }
+
+        let Some(endpoint) = self.endpoint.as_deref() else {
+            return Ok(None);
+        };
+
+        let response: Value = client
+            .get(build_endpoint_url(endpoint, term))
+            .send()
+            .await
+            .context("Image search request failed")?
+            .error_for_status()
+            .context("Image search returned error")?
+            .json()
+            .await
+            .context("Image search response parsing failed")?;
+
+        let Some(url) = extract_image_url(&response, self.json_pointer.as_deref()) else {
+            return Ok(None);
+        };
+
+        let data = client
+            .get(&url)
+            .send()
+            .await
+            .context("Image download failed")?
+            .error_for_status()
+            .context("Image host returned error")?
+            .bytes()
+            .await
+            .context("Image download failed")?;
+
+        if data.is_empty() {
+            return Err(anyhow!("Image at '{url}' is empty"));
+        }
+
+        Ok(Some(ImageFile {
+            extension: extension_of(&url),
+            data: data.to_vec(),
+        }))
+    }
+}
+
+fn build_endpoint_url(endpoint: &str, term: &str) -> String {
+    let encoded = urlencoding::encode(term);
+    if endpoint.contains("{term}") {
+        endpoint.replace("{term}", &encoded)
+    } else {
+        let separator = if endpoint.contains('?') { '&' } else { '?' };
+        format!("{endpoint}{separator}q={encoded}")
+    }
+}
+
+fn extract_image_url(value: &Value, pointer: Option<&str>) -> Option<String> {
+    match pointer {
+        Some(pointer) => value
+            .pointer(pointer)
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        None => first_url(value),
+    }
+}
+
+fn first_url(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) if text.starts_with("http://") || text.starts_with("https://") => {
+            Some(text.clone())
+        }
+        Value::Array(items) => items.iter().find_map(first_url),
+        Value::Object(map) => map.values().find_map(first_url),
+        _ => None,
+    }
+}
+
+fn find_local_image(dir: &Path, term: &str) -> Option<PathBuf> {
+    let lower = term.trim().to_lowercase();
+    let stems = [
+        term.trim().to_string(),
+        lower.clone(),
+        lower.replace(' ', "_"),
+        lower.replace(' ', "-"),
+    ];
+
+    stems.iter().find_map(|stem| {
+        IMAGE_EXTENSIONS
+            .iter()
+            .map(|ext| dir.join(format!("{stem}.{ext}")))
+            .find(|path| path.is_file())
+    })
+}
+
+fn extension_of(path_or_url: &str) -> String {
+    let path = path_or_url.split(['?', '#']).next().unwrap_or(path_or_url);
+
+    path.rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase())
+        .filter(|ext| IMAGE_EXTENSIONS.contains(&ext.as_str()))
+        .unwrap_or_else(|| "jpg".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn builds_endpoint_url_with_and_without_placeholder() {
+        assert_eq!(
+            build_endpoint_url("http://localhost:9000/img/{term}", "ice cream"),
+            "http://localhost:9000/img/ice%20cream"
+        );
+        assert_eq!(
+            build_endpoint_url("http://localhost:9000/search?n=1", "apple"),
+            "http://localhost:9000/search?n=1&q=apple"
+        );
+    }
+
+    #[test]
+    fn extracts_image_url_from_json() {
+        let response = json!({
+            "total": 2,
+            "results": [
+                { "id": 1, "urls": { "small": "https://img.example/1.png" } },
+                { "id": 2, "urls": { "small": "https://img.example/2.png" } }
+            ]
+        });
+
+        assert_eq!(
+            extract_image_url(&response, None).as_deref(),
+            Some("https://img.example/1.png")
+        );
+        assert_eq!(
+            extract_image_url(&response, Some("/results/1/urls/small")).as_deref(),
+            Some("https://img.example/2.png")
+        );
+        assert_eq!(extract_image_url(&response, Some("/missing")), None);
+    }
+
+    #[test]
+    fn finds_local_image_by_term() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("ice_cream.png"), b"png").unwrap();
+
+        let found = find_local_image(dir.path(), "Ice Cream").unwrap();
+        assert_eq!(found, dir.path().join("ice_cream.png"));
+        assert_eq!(extension_of(found.to_string_lossy().as_ref()), "png");
+        assert!(find_local_image(dir.path(), "banana").is_none());
+    }
+}
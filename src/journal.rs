@@ -0,0 +1,263 @@
+use std::{
+    cmp::Reverse,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+use crate::anki::AnkiConnect;
+
+/// Directory of run journals inside the data directory.
+pub const JOURNAL_DIR: &str = "runs";
+
+/// Runs kept before the oldest journals are removed.
+const KEEP_RUNS: usize = 50;
+
+/// Everything one invocation changed in Anki.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Run {
+    /// Start time in Unix milliseconds; also the journal's file name.
+    pub id: u64,
+    pub command: String,
+    pub changes: Vec<Change>,
+    /// Unix time the run was undone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub undone_at: Option<u64>,
+}
+
+/// A change to Anki that `undo` can reverse. No command edits the fields of
+/// existing notes, so creating notes and renaming tags cover everything.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Change {
+    Created {
+        note_id: u64,
+    },
+    Retagged {
+        note_ids: Vec<u64>,
+        from: String,
+        to: String,
+    },
+}
+
+impl Run {
+    pub fn created(&self) -> usize {
+        self.changes
+            .iter()
+            .filter(|change| matches!(change, Change::Created { .. }))
+            .count()
+    }
+
+    pub fn retagged(&self) -> usize {
+        self.changes
+            .iter()
+            .map(|change| match change {
+                Change::Retagged { note_ids, .. } => note_ids.len(),
+                Change::Created { .. } => 0,
+            })
+            .sum()
+    }
+
+    /// One line for the run list, e.g. `3 h ago  notaforge --words w.txt  (12 created)`.
+    pub fn summary(&self) -> String {
+        let mut counts = Vec::new();
+        if self.created() > 0 {
+            counts.push(format!("{} created", self.created()));
+        }
+        if self.retagged() > 0 {
+            counts.push(format!("{} retagged", self.retagged()));
+        }
+        if self.undone_at.is_some() {
+            counts.push("undone".to_string());
+        }
+        format!(
+            "{}  {}  ({})",
+            age(now_secs().saturating_sub(self.id / 1000)),
+            self.command,
+            counts.join(", ")
+        )
+    }
+}
+
+/// Records the notes a run creates or changes, including those added through
+/// the proxy, so `undo` can reverse them. Nothing is written until the first
+/// change.
+pub struct Journal {
+    dir: PathBuf,
+    run: Mutex<Run>,
+}
+
+impl Journal {
+    pub fn start(dir: PathBuf, command: String) -> Self {
+        let id = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or_default();
+        Self {
+            dir,
+            run: Mutex::new(Run {
+                id,
+                command,
+                changes: Vec::new(),
+                undone_at: None,
+            }),
+        }
+    }
+
+    pub fn created(&self, note_id: u64) -> Result<()> {
+        self.record(Change::Created { note_id })
+    }
+
+    pub fn retagged(&self, note_ids: &[u64], from: &str, to: &str) -> Result<()> {
+        self.record(Change::Retagged {
+            note_ids: note_ids.to_vec(),
+            from: from.to_string(),
+            to: to.to_string(),
+        })
+    }
+
+    fn record(&self, change: Change) -> Result<()> {
+        let mut run = self.run.lock().unwrap_or_else(|err| err.into_inner());
+        let first = run.changes.is_empty();
+        run.changes.push(change);
+        save(&self.dir, &run)?;
+        if first {
+            prune(&self.dir)?;
+        }
+        Ok(())
+    }
+}
+
+/// Recorded runs, newest first.
+pub fn list(dir: &Path) -> Result<Vec<Run>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut runs = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("failed to read '{}'", dir.display()))? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            let raw = fs::read_to_string(&path)
+                .with_context(|| format!("failed to read '{}'", path.display()))?;
+            runs.push(
+                serde_json::from_str::<Run>(&raw)
+                    .with_context(|| format!("failed to parse '{}'", path.display()))?,
+            );
+        }
+    }
+    runs.sort_by_key(|run| Reverse(run.id));
+    Ok(runs)
+}
+
+/// Deletes the notes the run created and puts back the tags it replaced,
+/// newest change first, then marks the run undone.
+pub async fn undo(client: &AnkiConnect, dir: &Path, run: &mut Run) -> Result<()> {
+    if run.undone_at.is_some() {
+        return Err(anyhow!("that run was already undone"));
+    }
+    client.check().await?;
+
+    let created: Vec<u64> = run
+        .changes
+        .iter()
+        .filter_map(|change| match change {
+            Change::Created { note_id } => Some(*note_id),
+            Change::Retagged { .. } => None,
+        })
+        .collect();
+    for change in run.changes.iter().rev() {
+        if let Change::Retagged { note_ids, from, to } = change {
+            client.replace_tags(note_ids, to, from).await?;
+        }
+    }
+    if !created.is_empty() {
+        client.delete_notes(&created).await?;
+    }
+
+    run.undone_at = Some(now_secs());
+    save(dir, run)?;
+    println!(
+        "Deleted {} note(s) and restored the tags of {} note(s).",
+        created.len(),
+        run.retagged()
+    );
+    Ok(())
+}
+
+fn save(dir: &Path, run: &Run) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("failed to create '{}'", dir.display()))?;
+    let path = dir.join(format!("{}.json", run.id));
+    let temp = path.with_extension("tmp");
+    fs::write(&temp, serde_json::to_string_pretty(run)?)
+        .and_then(|()| fs::rename(&temp, &path))
+        .with_context(|| format!("failed to write '{}'", path.display()))
+}
+
+/// Removes all but the newest [`KEEP_RUNS`] journals.
+fn prune(dir: &Path) -> Result<()> {
+    for run in list(dir)?.iter().skip(KEEP_RUNS) {
+        let path = dir.join(format!("{}.json", run.id));
+        fs::remove_file(&path).with_context(|| format!("failed to remove '{}'", path.display()))?;
+    }
+    Ok(())
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+fn age(secs: u64) -> String {
+    match secs {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{} min ago", secs / 60),
+        3600..86_400 => format!("{} h ago", secs / 3600),
+        _ => format!("{} days ago", secs / 86_400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_runs_with_changes_newest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().join(JOURNAL_DIR);
+
+        let idle = Journal::start(dir.clone(), "notaforge -w known".to_string());
+        assert!(list(&dir).unwrap().is_empty());
+        drop(idle);
+
+        let first = Journal::start(dir.clone(), "notaforge --words w.txt".to_string());
+        first.created(1001).unwrap();
+        first.created(1002).unwrap();
+        let second = Journal {
+            dir: dir.clone(),
+            run: Mutex::new(Run {
+                id: first.run.lock().unwrap().id + 1,
+                command: "notaforge migrate-tags".to_string(),
+                changes: Vec::new(),
+                undone_at: None,
+            }),
+        };
+        second.retagged(&[7, 8], "term:term", "term:日本").unwrap();
+
+        let runs = list(&dir).unwrap();
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0].command, "notaforge migrate-tags");
+        assert_eq!(runs[0].retagged(), 2);
+        assert_eq!(runs[1].created(), 2);
+        assert!(
+            runs[1]
+                .summary()
+                .ends_with("notaforge --words w.txt  (2 created)")
+        );
+    }
+}
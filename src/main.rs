@@ -1,70 +1,428 @@
 mod anki;
+mod base_health;
 mod card_template;
+mod clipboard;
+mod clips;
 mod config;
+mod config_cmd;
+mod duplicates;
+mod enrichment;
+mod error;
+mod filter;
+mod images;
+mod journal;
+mod migrate;
+mod mining;
+mod output;
+mod pipeline;
+mod proxy;
+mod queue;
+mod server;
+mod subtitles;
+mod translation;
 mod vocab_service;
 use anki::*;
-use ankiconnect_rs::{
-    AnkiClient, DuplicateScope, NoteBuilder,
-    builders::{Query, QueryBuilder},
+use ankiconnect_rs::Deck;
+use anyhow::{Context, Result, anyhow};
+use base_health::BaseHealth;
+use card_template::ExampleSentence;
+use clap::{Parser, Subcommand, ValueEnum};
+use clipboard::{Clipboard, Debouncer, extract_term};
+use clips::ClipExtractor;
+use config::{AppConfig, LayeredConfig};
+use duplicates::{DuplicateScope, Duplicates};
+use enrichment::Enrichment;
+use filter::{KnownWordsFilter, load_known_words, read_word_list};
+use images::ImageLookup;
+use journal::Journal;
+use mining::{extract_candidates, load_sentences, parse_selection};
+use output::{OutputFormat, print_failure, print_report};
+use pipeline::{Pipeline, TemplateKind, TermReport};
+use proxy::ProxyOptions;
+use queue::NoteQueue;
+use std::{
+    env,
+    io::{self, Write},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::atomic::Ordering,
+    time::Duration,
 };
-use anyhow::{Result, anyhow};
-use card_template::{CardFields, CardTemplate, SimpleCard, VocabularyCard};
-use clap::{Parser, ValueEnum};
-use std::{env, path::PathBuf};
-use vocab_service::build_vocabulary_card;
+use translation::Translator;
+use vocab_service::{DEFAULT_MAX_EXAMPLES, LookupOptions};
 
 #[derive(Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    subcommand_negates_reqs = true,
+    after_help = "Exit codes: 0 success, 1 other error, 3 AnkiConnect unreachable, \
+4 deck or model missing, 5 lookup provider failure (or incomplete card with --strict), \
+6 term already in the deck, 7 AnkiConnect rejected the API key, is too old \
+or is not what answers at anki_url"
+)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Path to the configuration file (TOML)
-    #[arg(long)]
+    #[arg(long, global = true)]
     config: Option<PathBuf>,
 
+    /// Named profile from the config file (`[profiles.<name>]`) to use;
+    /// also read from NOTAFORGE_PROFILE
+    #[arg(long, global = true)]
+    profile: Option<String>,
+
+    /// AnkiConnect URL (default: http://127.0.0.1:8765)
+    #[arg(long, global = true)]
+    anki_url: Option<String>,
+
+    /// Value of AnkiConnect's apiKey setting; prefer NOTAFORGE_ANKI_API_KEY,
+    /// which keeps the key out of the process list
+    #[arg(long, global = true)]
+    anki_api_key: Option<String>,
+
+    /// Timeout for each AnkiConnect request in milliseconds (default: 15000)
+    #[arg(long, global = true)]
+    anki_timeout_ms: Option<u64>,
+
     /// Name of the Anki deck to use
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     deck: Option<String>,
 
-    /// Name of the Anki model to use (default: Basic)
-    #[arg(short, long)]
+    /// Name of the Anki model to use (default: Basic, or Cloze for the cloze template)
+    #[arg(short, long, global = true)]
     model: Option<String>,
 
     /// Card template to use when generating fields
-    #[arg(short, long, value_enum)]
+    #[arg(short, long, value_enum, global = true)]
     template: Option<TemplateKind>,
 
+    /// Where existing notes count as duplicates (default: deck)
+    #[arg(long, value_enum, global = true)]
+    duplicate_scope: Option<DuplicateScope>,
+
     /// Term to build a card for
-    #[arg(short = 'w', long)]
-    term: String,
+    #[arg(
+        short = 'w',
+        long,
+        required_unless_present = "words",
+        conflicts_with = "words"
+    )]
+    term: Option<String>,
 
-    /// Source language code used for translation lookups
+    /// File with one term per line to build cards for (`-` reads stdin)
     #[arg(long)]
+    words: Option<PathBuf>,
+
+    /// Only print the terms of the word list that survive the known-words filter
+    #[arg(long, requires = "words")]
+    filter_only: bool,
+
+    /// Source language code used for translation lookups
+    #[arg(long, global = true)]
     source_lang: Option<String>,
 
-    /// Target language code used for translation lookups
+    /// Target language code(s) used for translation lookups; repeat or
+    /// separate with commas for bilingual cards (e.g. ru,uk)
+    #[arg(long, global = true, value_delimiter = ',')]
+    target_lang: Vec<String>,
+
+    /// Maximum number of retries for translation API calls (default: 2)
+    #[arg(long, global = true)]
+    translate_retries: Option<u32>,
+
+    /// Base backoff in milliseconds for translation retries (default: 500)
+    #[arg(long, global = true)]
+    translate_backoff_ms: Option<u64>,
+
+    /// Maximum number of example sentences to put on a card (default: 3)
+    #[arg(long, global = true)]
+    max_examples: Option<usize>,
+
+    /// Skip the image lookup even when an image source is configured
+    #[arg(long, global = true)]
+    no_images: bool,
+
+    /// Also create a production card (translation on the front, term on the back)
+    #[arg(long, global = true)]
+    reverse: bool,
+
+    /// Refuse to add cards when a lookup provider failed, instead of tagging them incomplete
+    #[arg(long, global = true)]
+    strict: bool,
+
+    /// How to report results of added terms
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text, global = true)]
+    output: OutputFormat,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Extract candidate vocabulary from a text, EPUB or subtitle file
+    Mine(MineArgs),
+    /// Create cards from a subtitle line, with audio and a screenshot cut from the video
+    Clip(ClipArgs),
+    /// Serve the card pipeline as a local HTTP/JSON API
+    Serve(ServeArgs),
+    /// Create cards for words copied to the clipboard
+    WatchClipboard(WatchArgs),
+    /// Act as an AnkiConnect proxy that enriches notes added by other tools
+    Proxy(ProxyArgs),
+    /// Add the notes queued while Anki was unreachable
+    Sync,
+    /// Delete the notes a recent run created and undo its retagging; lists
+    /// the recorded runs to choose from
+    Undo(UndoArgs),
+    /// Retag notes whose term tags were made before non-Latin terms got tags
+    /// of their own
+    MigrateTags {
+        /// Print the changes without making them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Inspect or create the configuration file
+    #[command(subcommand)]
+    Config(ConfigCommand),
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Report unknown keys, invalid templates, unreachable translation bases
+    /// and missing decks or models
+    Check,
+    /// Print the effective settings and where each one came from
+    Show,
+    /// Write a fully commented default config to the config path
+    Init {
+        /// Overwrite an existing file
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(clap::Args)]
+struct UndoArgs {
+    /// Number of the run to undo, as shown by --list; asked for when omitted
+    run: Option<usize>,
+
+    /// Only list the recorded runs
     #[arg(long)]
-    target_lang: Option<String>,
+    list: bool,
+}
+
+#[derive(clap::Args)]
+struct ProxyArgs {
+    /// Address to listen on; point the dictionary extension here
+    #[arg(long, default_value = proxy::DEFAULT_LISTEN)]
+    listen: SocketAddr,
 
-    /// Maximum number of retries for translation API calls
-    #[arg(long, default_value_t = 2)]
-    translate_retries: u32,
+    /// AnkiConnect URL to forward requests to (default: the anki_url setting)
+    #[arg(long)]
+    upstream: Option<String>,
+}
 
-    /// Base backoff in milliseconds for translation retries
+#[derive(clap::Args)]
+struct WatchArgs {
+    /// How often to read the clipboard, in milliseconds
     #[arg(long, default_value_t = 500)]
-    translate_backoff_ms: u64,
+    interval_ms: u64,
+
+    /// How long the clipboard must stay unchanged before its content is used
+    #[arg(long, default_value_t = 800)]
+    debounce_ms: u64,
+
+    /// Ignore clipboard content with more words than this
+    #[arg(long, default_value_t = 3)]
+    max_words: usize,
+
+    /// Only log results instead of also showing desktop notifications
+    #[arg(long)]
+    no_notify: bool,
+}
+
+#[derive(clap::Args)]
+struct ServeArgs {
+    /// Address to listen on
+    #[arg(long, default_value = server::DEFAULT_LISTEN)]
+    listen: SocketAddr,
 }
 
-#[derive(Copy, Clone, Debug, ValueEnum)]
-enum TemplateKind {
-    Vocabulary,
-    Simple,
+#[derive(clap::Args)]
+struct MineArgs {
+    /// Document to mine (.txt, .epub, .srt or .vtt)
+    file: PathBuf,
+
+    /// Number of candidates to offer
+    #[arg(long, default_value_t = 30)]
+    limit: usize,
+
+    /// Candidates to turn into cards, e.g. `1,3,5-7` or `all`; prompts when omitted
+    #[arg(long)]
+    pick: Option<String>,
+}
+
+#[derive(clap::Args)]
+struct ClipArgs {
+    /// Subtitle file (.srt or .vtt)
+    subtitles: PathBuf,
+
+    /// Local video the subtitles belong to
+    #[arg(long)]
+    video: Option<PathBuf>,
+
+    /// Subtitle line to use (the SRT counter); lists the lines when omitted
+    #[arg(long, requires_all = ["term", "video"])]
+    line: Option<usize>,
+
+    /// Term from the line to build the card for
+    #[arg(long)]
+    term: Option<String>,
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> ExitCode {
     let args = Args::parse();
+    match run(args).await {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("Error: {err:?}");
+            ExitCode::from(error::ErrorKind::of(&err).map_or(1, error::ErrorKind::exit_code))
+        }
+    }
+}
+
+/// Settings given as flags, as `(config key, value, flag)`. Switches only
+/// count when they are set, so a config `reverse = true` is not undone by
+/// leaving out `--reverse`.
+fn cli_overrides(args: &Args) -> Vec<config::CliOverride> {
+    let mut overrides: Vec<config::CliOverride> = Vec::new();
+    let mut text = |key, value: &Option<String>, flag| {
+        if let Some(value) = value {
+            overrides.push((key, value.as_str().into(), flag));
+        }
+    };
+    text("anki_url", &args.anki_url, "--anki-url");
+    text("anki_api_key", &args.anki_api_key, "--anki-api-key");
+    text("deck", &args.deck, "--deck");
+    text("model", &args.model, "--model");
+    text("source_lang", &args.source_lang, "--source-lang");
+    if let Some(name) = args.template.and_then(|kind| kind.to_possible_value()) {
+        overrides.push(("template", name.get_name().into(), "--template"));
+    }
+    if let Some(name) = args
+        .duplicate_scope
+        .and_then(|scope| scope.to_possible_value())
+    {
+        overrides.push((
+            "duplicate_scope",
+            name.get_name().into(),
+            "--duplicate-scope",
+        ));
+    }
+    if !args.target_lang.is_empty() {
+        let langs = args.target_lang.iter().map(|lang| lang.as_str().into());
+        let langs = toml::Value::Array(langs.collect());
+        overrides.push(("target_lang", langs, "--target-lang"));
+    }
+    if let Some(timeout) = args.anki_timeout_ms {
+        overrides.push((
+            "anki_timeout_ms",
+            (timeout as i64).into(),
+            "--anki-timeout-ms",
+        ));
+    }
+    if let Some(retries) = args.translate_retries {
+        overrides.push((
+            "translate_retries",
+            i64::from(retries).into(),
+            "--translate-retries",
+        ));
+    }
+    if let Some(backoff) = args.translate_backoff_ms {
+        overrides.push((
+            "translate_backoff_ms",
+            (backoff as i64).into(),
+            "--translate-backoff-ms",
+        ));
+    }
+    if let Some(max) = args.max_examples {
+        overrides.push(("max_examples", (max as i64).into(), "--max-examples"));
+    }
+    if args.reverse {
+        overrides.push(("reverse", true.into(), "--reverse"));
+    }
+    if args.strict {
+        overrides.push(("strict", true.into(), "--strict"));
+    }
+    overrides
+}
+
+/// The invocation as recorded in the run journal, with the API key left out.
+fn command_line() -> String {
+    let mut words = vec!["notaforge".to_string()];
+    let mut secret = false;
+    for arg in env::args().skip(1) {
+        if secret {
+            words.push("********".to_string());
+        } else if arg.starts_with("--anki-api-key=") {
+            words.push("--anki-api-key=********".to_string());
+        } else {
+            words.push(arg.clone());
+        }
+        secret = arg == "--anki-api-key";
+    }
+    words.join(" ")
+}
+
+/// Lists the recorded runs and undoes the chosen one.
+async fn run_undo(client: &AnkiConnect, dir: &Path, args: &UndoArgs) -> Result<ExitCode> {
+    let mut runs = journal::list(dir)?;
+    if runs.is_empty() {
+        println!("No recorded runs.");
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let number = match args.run {
+        Some(number) if !args.list => number,
+        _ => {
+            for (number, run) in runs.iter().enumerate() {
+                println!("{:>3}. {}", number + 1, run.summary());
+            }
+            if args.list {
+                return Ok(ExitCode::SUCCESS);
+            }
+            print!("Run to undo (number; empty to cancel): ");
+            io::stdout().flush()?;
+            let mut line = String::new();
+            io::stdin().read_line(&mut line)?;
+            if line.trim().is_empty() {
+                return Ok(ExitCode::SUCCESS);
+            }
+            line.trim()
+                .parse()
+                .map_err(|_| anyhow!("'{}' is not a run number", line.trim()))?
+        }
+    };
+    if number == 0 || number > runs.len() {
+        return Err(anyhow!(
+            "no run {number}; there are {} recorded",
+            runs.len()
+        ));
+    }
+
+    journal::undo(client, dir, &mut runs[number - 1]).await?;
+    Ok(ExitCode::SUCCESS)
+}
 
-    let config_path = args
-        .config
+/// Config file location: `--config`, then `NOTAFORGE_CONFIG`, then the XDG
+/// config directory.
+fn resolve_config_path(args: &Args) -> PathBuf {
+    args.config
         .clone()
         .or_else(|| env::var_os("NOTAFORGE_CONFIG").map(PathBuf::from))
         .or_else(|| {
@@ -77,230 +435,603 @@ async fn main() -> Result<()> {
                 .map(PathBuf::from)
                 .map(|home| home.join(".config/notaforge/config.toml"))
         })
-        .unwrap_or_else(|| PathBuf::from("notaforge.toml"));
+        .unwrap_or_else(|| PathBuf::from("notaforge.toml"))
+}
 
-    let config = config::load(&config_path)?;
+async fn run(args: Args) -> Result<ExitCode> {
+    if let Some(Command::Clip(ClipArgs {
+        subtitles,
+        line: None,
+        ..
+    })) = &args.command
+    {
+        list_cues(subtitles)?;
+        return Ok(ExitCode::SUCCESS);
+    }
 
-    let deck_name = args
+    let config_path = resolve_config_path(&args);
+    match &args.command {
+        Some(Command::Config(ConfigCommand::Check)) => {
+            let passed = config_cmd::check(&config_path).await?;
+            return Ok(if passed {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            });
+        }
+        Some(Command::Config(ConfigCommand::Init { force })) => {
+            config_cmd::init(&config_path, *force)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        _ => {}
+    }
+
+    let profile = args
+        .profile
+        .clone()
+        .or_else(|| env::var("NOTAFORGE_PROFILE").ok());
+    let layered = LayeredConfig::load(
+        &config_path,
+        profile.as_deref(),
+        env::vars(),
+        cli_overrides(&args),
+    )?;
+    if let Some(Command::Config(ConfigCommand::Show)) = &args.command {
+        println!("# config file: {}", config_path.display());
+        if let Some(profile) = &profile {
+            println!("# profile: {profile}");
+        }
+        print!("{}", layered.render());
+        return Ok(ExitCode::SUCCESS);
+    }
+    let config = &layered.config;
+
+    let note_queue = NoteQueue::new(
+        config::data_dir()
+            .unwrap_or_default()
+            .join(queue::QUEUE_FILE),
+    );
+    let journal_dir = config::data_dir()
+        .unwrap_or_default()
+        .join(journal::JOURNAL_DIR);
+    if let Some(Command::Undo(undo)) = &args.command {
+        let client = AnkiConnect::from_config(config)?;
+        return run_undo(&client, &journal_dir, undo).await;
+    }
+    let journal = Journal::start(journal_dir, command_line());
+    let duplicates = Duplicates {
+        scope: match config.duplicate_scope.as_deref() {
+            Some(name) => DuplicateScope::from_str(name, true)
+                .map_err(|_| anyhow!("Invalid duplicate_scope '{}' in config", name))?,
+            None => DuplicateScope::Deck,
+        },
+        first_field: config.duplicate_first_field.unwrap_or(true),
+    };
+    if let Some(Command::Sync) = &args.command {
+        let client = AnkiConnect::from_config(config)?;
+        let left = queue::sync(&client, &note_queue, &duplicates, &journal).await?;
+        return Ok(if left == 0 {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        });
+    }
+
+    if let Some(Command::MigrateTags { dry_run }) = &args.command {
+        let client = AnkiConnect::from_config(config)?;
+        let unresolved = migrate::migrate_tags(&client, &note_queue, &journal, *dry_run).await?;
+        return Ok(if unresolved == 0 {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        });
+    }
+
+    let deck_name = config
         .deck
         .clone()
-        .or_else(|| config.deck.clone())
         .ok_or_else(|| anyhow!("Deck must be provided via CLI or config"))?;
 
-    let model_name = args
-        .model
-        .clone()
-        .or_else(|| config.model.clone())
-        .ok_or_else(|| anyhow!("Model must be provided via CLI or config"))?;
-
-    let template_kind = match args.template {
-        Some(kind) => kind,
-        None => match config.template.as_deref() {
-            Some(name) => TemplateKind::from_str(name, true)
-                .map_err(|_| anyhow!("Invalid template '{}' in config", name))?,
-            None => TemplateKind::Vocabulary,
-        },
+    let template_kind = match config.template.as_deref() {
+        Some(name) => TemplateKind::from_str(name, true)
+            .map_err(|_| anyhow!("Invalid template '{}' in config", name))?,
+        None => TemplateKind::Vocabulary,
+    };
+
+    // Cloze notes need Anki's Cloze note type, so the regular `model` key
+    // (usually a Basic model) only applies to the other templates, unless
+    // --model names one explicitly.
+    let model_name = match template_kind {
+        TemplateKind::Cloze => match layered.source("model") {
+            Some(config::Source::Cli(_)) => config.model.clone(),
+            _ => config.cloze_model.clone(),
+        }
+        .unwrap_or_else(|| "Cloze".to_string()),
+        _ => config
+            .model
+            .clone()
+            .ok_or_else(|| anyhow!("Model must be provided via CLI or config"))?,
     };
 
-    let source_lang = args
+    let source_lang = config
         .source_lang
         .clone()
-        .or_else(|| config.source_lang.clone())
         .unwrap_or_else(|| "en".to_string());
 
-    let target_lang = args
-        .target_lang
-        .clone()
-        .or_else(|| config.target_lang.clone())
-        .unwrap_or_else(|| "ru".to_string());
+    let target_langs = if config.target_lang.is_empty() {
+        vec!["ru".to_string()]
+    } else {
+        config.target_lang.clone()
+    };
 
-    let translate_retries = args
-        .translate_retries
-        .max(config.translate_retries.unwrap_or(args.translate_retries));
-    let translate_backoff_ms = args.translate_backoff_ms.max(
+    let http = reqwest::Client::new();
+    let translator = Translator::new(
+        http.clone(),
+        &config.translation_bases,
+        config.translate_retries.unwrap_or(2),
+        config.translate_backoff_ms.unwrap_or(500),
         config
-            .translate_backoff_ms
-            .unwrap_or(args.translate_backoff_ms),
+            .translate_concurrency
+            .unwrap_or(translation::DEFAULT_CONCURRENCY),
+        config
+            .translate_requests_per_second
+            .unwrap_or(translation::DEFAULT_REQUESTS_PER_SECOND),
+        config::cache_dir()
+            .map(|dir| BaseHealth::load(dir.join(base_health::HEALTH_FILE)))
+            .unwrap_or_default(),
     );
 
-    let translation_bases = if !config.translation_bases.is_empty() {
-        config.translation_bases.clone()
-    } else if let Some(base) = config.legacy_translation_base.clone() {
-        vec![base]
+    let reverse = config.reverse.unwrap_or(false);
+    if reverse && matches!(template_kind, TemplateKind::Cloze) {
+        return Err(anyhow!(
+            "Production cards need a Front/Back model and cannot be combined with the cloze template"
+        ));
+    }
+
+    let enrichment = Enrichment::load(
+        config.frequency_list.as_deref(),
+        config.cefr_list.as_deref(),
+    )?;
+
+    let images = if args.no_images {
+        ImageLookup::default()
     } else {
-        Vec::new()
+        ImageLookup {
+            endpoint: config.image_endpoint.clone(),
+            json_pointer: config.image_json_pointer.clone(),
+            directory: config.image_dir.clone(),
+        }
     };
+    let image_field = config
+        .image_field
+        .clone()
+        .unwrap_or_else(|| template_kind.field_names().1.to_string());
+    let audio_field = config
+        .audio_field
+        .clone()
+        .unwrap_or_else(|| template_kind.field_names().1.to_string());
 
-    let client = AnkiClient::new();
-    let deck = find_deck(&client, &deck_name)?;
-    let model = find_model(&client, &model_name)?;
-
-    let front_field = get_model_field(&model, "Front")?;
-    let back_field = get_model_field(&model, "Back")?;
+    // The proxy only forwards requests, so it has nothing to queue, and the
+    // extension's notes name their own deck and model.
+    let proxy = matches!(args.command, Some(Command::Proxy(_)));
+    let client = AnkiConnect::from_config(config)?;
+    let offline = match client.check().await {
+        Ok(_) => false,
+        Err(err) if queue::is_offline(&err) && !proxy => {
+            eprintln!(
+                "Warning: {err}.\nNotes will be queued in '{}'; run `notaforge sync` once Anki is reachable.",
+                note_queue.path().display()
+            );
+            true
+        }
+        Err(err) => return Err(err),
+    };
+    let (deck, model) = if offline || proxy {
+        let (front, back) = template_kind.field_names();
+        let fields = [front, back, image_field.as_str(), audio_field.as_str()];
+        (
+            Deck::new(0, deck_name),
+            offline_model(&model_name, &fields)?,
+        )
+    } else {
+        let queued = note_queue.load()?.len();
+        if queued > 0 {
+            eprintln!(
+                "{queued} note(s) were queued while Anki was unreachable; run `notaforge sync` to add them."
+            );
+        }
+        (
+            find_deck(&client, &deck_name).await?,
+            find_model(&client, &model_name).await?,
+        )
+    };
 
-    let term_tag = build_term_tag(&args.term);
-    let duplicate_query = build_duplicate_query(deck.name(), &term_tag);
+    let pipeline = Pipeline {
+        client,
+        http,
+        deck,
+        model,
+        template: template_kind,
+        reverse,
+        lookup: LookupOptions {
+            source_lang,
+            target_langs,
+            max_examples: config.max_examples.unwrap_or(DEFAULT_MAX_EXAMPLES),
+            translator,
+        },
+        enrichment,
+        images,
+        image_field,
+        audio_field,
+        extra_tags: config.extra_tags.clone(),
+        strict: config.strict.unwrap_or(false),
+        duplicates,
+        card_cache: Default::default(),
+        queue: note_queue.into(),
+        journal,
+        offline: offline.into(),
+    };
+    if !proxy {
+        pipeline.check_fields()?;
+    }
 
-    if !client.cards().find(&duplicate_query)?.is_empty() {
-        println!(
-            "Note for term '{}' already exists in deck '{}'; skipping.",
-            args.term,
-            deck.name()
-        );
-        return Ok(());
-    }
-
-    let http_client = reqwest::Client::new();
-    let vocabulary_card = build_vocabulary_card(
-        &http_client,
-        &args.term,
-        &source_lang,
-        &target_lang,
-        &translation_bases,
-        translate_retries,
-        translate_backoff_ms,
-    )
-    .await?;
+    match &args.command {
+        Some(Command::Mine(mine)) => {
+            let filter = known_words_filter(config, &pipeline)?;
+            return run_mine(&pipeline, &filter, mine, args.output).await;
+        }
+        Some(Command::Clip(clip)) => {
+            let extractor = ClipExtractor {
+                ffmpeg: config
+                    .ffmpeg
+                    .clone()
+                    .unwrap_or_else(|| ClipExtractor::default().ffmpeg),
+                padding: Duration::from_millis(
+                    config.clip_padding_ms.unwrap_or(clips::DEFAULT_PADDING_MS),
+                ),
+            };
+            return run_clip(&pipeline, &extractor, clip, args.output).await;
+        }
+        Some(Command::Serve(serve)) => {
+            server::serve(pipeline, serve.listen).await?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::Proxy(proxy)) => {
+            let options = ProxyOptions {
+                upstream: proxy
+                    .upstream
+                    .clone()
+                    .unwrap_or_else(|| pipeline.client.url().to_string()),
+                term_field: config.proxy_term_field.clone(),
+                enrich_field: config
+                    .proxy_enrich_field
+                    .clone()
+                    .unwrap_or_else(|| template_kind.field_names().1.to_string()),
+            };
+            proxy::serve(pipeline, options, proxy.listen).await?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::WatchClipboard(watch)) => {
+            let clipboard = Clipboard::new(config.clipboard_command.as_deref())?;
+            watch_clipboard(&pipeline, &clipboard, watch).await?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        // Handled before the pipeline is built.
+        Some(
+            Command::Config(_) | Command::Sync | Command::Undo(_) | Command::MigrateTags { .. },
+        )
+        | None => {}
+    }
 
-    let mut fields = match template_kind {
-        TemplateKind::Vocabulary => vocabulary_card.render(),
-        TemplateKind::Simple => render_simple_fields(&vocabulary_card),
+    let Some(words_path) = &args.words else {
+        let term = args.term.as_deref().unwrap_or_default();
+        return Ok(add_terms(&pipeline, args.output, vec![(term.to_string(), None)]).await);
     };
 
-    if !fields.tags.iter().any(|tag| tag == &term_tag) {
-        fields.tags.push(term_tag.clone());
-    }
+    let filter = known_words_filter(config, &pipeline)?;
+    let terms = filter.apply(read_word_list(words_path)?).await?;
 
-    for tag in &config.extra_tags {
-        if !fields.tags.iter().any(|existing| existing == tag) {
-            fields.tags.push(tag.clone());
+    if args.filter_only {
+        for term in &terms {
+            println!("{term}");
         }
+        return Ok(ExitCode::SUCCESS);
     }
 
-    let mut builder = NoteBuilder::new(model.clone())
-        .with_field_raw(front_field, &fields.front)
-        .with_field_raw(back_field, &fields.back);
+    Ok(add_terms(
+        &pipeline,
+        args.output,
+        terms.into_iter().map(|term| (term, None)).collect(),
+    )
+    .await)
+}
 
-    for tag in &fields.tags {
-        builder = builder.with_tag(tag);
+fn known_words_filter<'a>(
+    config: &AppConfig,
+    pipeline: &'a Pipeline,
+) -> Result<KnownWordsFilter<'a>> {
+    let known_words = match &config.known_words_file {
+        Some(path) => Some((path.clone(), load_known_words(path)?)),
+        None => None,
+    };
+    let mut known_decks = vec![pipeline.deck.name().to_string()];
+    for deck in &config.known_decks {
+        if !known_decks.contains(deck) {
+            known_decks.push(deck.clone());
+        }
+    }
+    if pipeline.offline.load(Ordering::Relaxed) {
+        eprintln!("Warning: Anki is unreachable, so known words are not checked against decks.");
+        known_decks.clear();
     }
 
-    let note = builder.build()?;
+    Ok(KnownWordsFilter {
+        client: &pipeline.client,
+        enrichment: &pipeline.enrichment,
+        known_words,
+        max_frequency_rank: config.skip_frequency_rank,
+        decks: known_decks,
+        field: config
+            .known_field
+            .clone()
+            .unwrap_or_else(|| pipeline.template.field_names().0.to_string()),
+    })
+}
 
-    // Add the note to the first deck
-    match client
-        .cards()
-        .add_note(&deck, note, false, Some(DuplicateScope::Deck))
-    {
-        Ok(note_id) => {
-            println!("Added note with ID: {}", note_id.value());
-            Ok(())
+/// Adds each term in turn, reporting failures without stopping the batch.
+///
+/// The exit code is the first failure's, or [`error::EXIT_DUPLICATE`] when
+/// nothing failed but some term was already in the deck.
+async fn add_terms(
+    pipeline: &Pipeline,
+    output: OutputFormat,
+    terms: Vec<(String, Option<ExampleSentence>)>,
+) -> ExitCode {
+    let total = terms.len();
+    let mut failed = 0;
+    let mut code = None;
+    let mut duplicate = false;
+    let mut record = |term: &str, result: Result<TermReport>| match result {
+        Ok(report) => {
+            duplicate |= report.is_duplicate();
+            print_report(
+                output,
+                &pipeline.duplicates.describe(pipeline.deck.name()),
+                &report,
+            );
+            if report.has_failures() {
+                failed += 1;
+                code.get_or_insert(1);
+            }
         }
-        Err(err)
-            if err.to_string().to_lowercase().contains("duplicate note")
-                || err.to_string().to_lowercase().contains("duplicate") =>
+        Err(err) => {
+            print_failure(output, term, &err);
+            failed += 1;
+            code.get_or_insert(error::ErrorKind::of(&err).map_or(1, error::ErrorKind::exit_code));
+        }
+    };
+
+    if total > 1 && !pipeline.offline.load(Ordering::Relaxed) {
+        let mut reported = 0;
+        let result = pipeline
+            .add_terms_bulk(terms.clone(), |term, result| {
+                reported += 1;
+                record(term, result);
+            })
+            .await;
+        // Terms after a failed chunk are reported with its error.
+        if let Err(err) = result
+            && let Some(((first, _), rest)) = terms[reported..].split_first()
         {
-            println!(
-                "Note for term '{}' already exists in deck '{}'; skipping.",
-                args.term,
-                deck.name()
-            );
-            Ok(())
+            let message = format!("{err:#}");
+            record(first, Err(err));
+            for (term, _) in rest {
+                record(term, Err(anyhow!("{message}")));
+            }
+        }
+    } else {
+        for (term, context) in terms {
+            let result = pipeline.add_term(&term, context).await;
+            record(&term, result);
         }
-        Err(err) => Err(err.into()),
     }
-}
-
-fn render_simple_fields(card: &VocabularyCard) -> CardFields {
-    let mut tags = card.extra_tags.clone();
-    if !card.part_of_speech.is_empty() {
-        tags.push(card.part_of_speech.clone());
+    if failed > 0 && total > 1 && output == OutputFormat::Text {
+        eprintln!("{failed} of {total} terms failed");
     }
 
-    let synonyms_block = if card.translation_synonyms.is_empty() {
-        String::new()
-    } else {
-        format!(
-            "<div style=\"margin-top:0.6em; color:#5e84c1;\">{}</div>",
-            card.translation_synonyms
-        )
-    };
-
-    SimpleCard {
-        front: format!("<b>{}</b>", card.term),
-        back: format!(
-            concat!(
-                "<div style=\"font-size:1.2em;\">{translation}</div>",
-                "{synonyms}",
-                "<div style=\"margin-top:0.8em; color:#666;\">{usage}</div>",
-            ),
-            translation = card.translation_heading,
-            synonyms = synonyms_block,
-            usage = card.translation_usage,
-        ),
-        tags,
+    match code {
+        Some(code) => ExitCode::from(code),
+        None if duplicate => ExitCode::from(error::EXIT_DUPLICATE),
+        None => ExitCode::SUCCESS,
     }
-    .render()
 }
 
-fn build_term_tag(term: &str) -> String {
-    let mut slug = String::with_capacity(term.len());
-    let mut last_was_sep = false;
-
-    for c in term.chars() {
-        if c.is_ascii_alphanumeric() {
-            slug.push(c.to_ascii_lowercase());
-            last_was_sep = false;
-        } else if c.is_whitespace() || matches!(c, '-' | '_' | ':' | '/' | '\\') {
-            if !last_was_sep && !slug.is_empty() {
-                slug.push('_');
-            }
-            last_was_sep = true;
-        } else {
-            if !last_was_sep && !slug.is_empty() {
-                slug.push('_');
-            }
-            last_was_sep = true;
+async fn run_mine(
+    pipeline: &Pipeline,
+    filter: &KnownWordsFilter<'_>,
+    args: &MineArgs,
+    output: OutputFormat,
+) -> Result<ExitCode> {
+    let sentences = load_sentences(&args.file)?;
+
+    let mut offered = Vec::new();
+    for candidate in extract_candidates(&sentences, &pipeline.enrichment) {
+        if offered.len() >= args.limit {
+            break;
+        }
+        if filter.check(&candidate.lemma).await?.is_none() {
+            offered.push(candidate);
         }
     }
 
-    if slug.ends_with('_') {
-        slug.pop();
+    if offered.is_empty() {
+        println!("No unknown words found in '{}'.", args.file.display());
+        return Ok(ExitCode::SUCCESS);
     }
 
-    if slug.is_empty() {
-        slug.push_str("term");
+    for (number, candidate) in offered.iter().enumerate() {
+        let rank = candidate
+            .frequency_rank
+            .map(|rank| format!(", #{rank}"))
+            .unwrap_or_default();
+        println!(
+            "{:>3}. {} ({}×{})  {}",
+            number + 1,
+            candidate.lemma,
+            candidate.count,
+            rank,
+            candidate.example.sentence
+        );
     }
 
-    format!("term:{}", slug)
+    let selection = match &args.pick {
+        Some(pick) => pick.clone(),
+        None => {
+            print!("Words to add (e.g. 1,3,5-7 or all; empty to cancel): ");
+            io::stdout().flush()?;
+            let mut line = String::new();
+            io::stdin().read_line(&mut line)?;
+            line
+        }
+    };
+
+    let terms = parse_selection(&selection, offered.len())?
+        .into_iter()
+        .map(|index| {
+            let candidate = &offered[index];
+            (candidate.lemma.clone(), Some(candidate.example.clone()))
+        })
+        .collect();
+
+    Ok(add_terms(pipeline, output, terms).await)
 }
 
-fn build_duplicate_query(deck_name: &str, term_tag: &str) -> Query {
-    QueryBuilder::new()
-        .in_deck(deck_name)
-        .and()
-        .has_tag("auto-generated")
-        .and()
-        .has_tag(term_tag)
-        .build()
+fn list_cues(path: &std::path::Path) -> Result<()> {
+    let raw = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read subtitles '{}'", path.display()))?;
+    for cue in subtitles::parse(&raw) {
+        let seconds = cue.start.as_secs();
+        println!(
+            "{:>5}  {:02}:{:02}:{:02}  {}",
+            cue.index,
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60,
+            cue.text
+        );
+    }
+    Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+async fn run_clip(
+    pipeline: &Pipeline,
+    extractor: &ClipExtractor,
+    args: &ClipArgs,
+    output: OutputFormat,
+) -> Result<ExitCode> {
+    let (Some(line), Some(term), Some(video)) = (args.line, &args.term, &args.video) else {
+        return Err(anyhow!("--line, --term and --video are required"));
+    };
 
-    #[test]
-    fn term_tag_slugifies_special_chars() {
-        assert_eq!(build_term_tag("taken aback"), "term:taken_aback");
-        assert_eq!(build_term_tag("  Weird-term?! "), "term:weird_term");
-    }
+    let raw = std::fs::read_to_string(&args.subtitles)
+        .with_context(|| format!("failed to read subtitles '{}'", args.subtitles.display()))?;
+    let cues = subtitles::parse(&raw);
+    let cue = cues
+        .iter()
+        .find(|cue| cue.index == line)
+        .ok_or_else(|| anyhow!("Subtitle line {line} not found"))?;
 
-    #[test]
-    fn duplicate_query_matches_expected_structure() {
-        let query = build_duplicate_query("My Deck", "term:word");
-        assert_eq!(
-            query.as_str(),
-            "deck:\"My Deck\" tag:auto\\-generated tag:term\\:word"
+    if !cue.text.to_lowercase().contains(&term.to_lowercase()) {
+        eprintln!(
+            "Warning: '{term}' does not appear in line {line}: {}",
+            cue.text
         );
     }
+
+    let clip = extractor.extract(video, cue).await?;
+    let example = ExampleSentence {
+        sentence: cue.text.clone(),
+        ..ExampleSentence::default()
+    };
+
+    let report = pipeline
+        .add_term_with_clip(term, Some(example), Some(&clip))
+        .await?;
+    print_report(
+        output,
+        &pipeline.duplicates.describe(pipeline.deck.name()),
+        &report,
+    );
+
+    Ok(if report.is_duplicate() {
+        ExitCode::from(error::EXIT_DUPLICATE)
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+async fn watch_clipboard(
+    pipeline: &Pipeline,
+    clipboard: &Clipboard,
+    args: &WatchArgs,
+) -> Result<()> {
+    let mut debouncer = Debouncer::new(Duration::from_millis(args.debounce_ms));
+    let mut interval = tokio::time::interval(Duration::from_millis(args.interval_ms));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    // Whatever was copied before starting shouldn't become a card.
+    debouncer.skip(clipboard.read().await?);
+
+    println!("Watching the clipboard; press Ctrl-C to stop.");
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+            _ = interval.tick() => {}
+        }
+
+        let copied = match clipboard.read().await {
+            Ok(copied) => copied,
+            Err(err) => {
+                eprintln!("Failed to read the clipboard: {err:#}");
+                continue;
+            }
+        };
+        let Some(text) = debouncer.observe(copied, std::time::Instant::now()) else {
+            continue;
+        };
+        let Some(term) = extract_term(&text, args.max_words) else {
+            continue;
+        };
+
+        let (summary, body) = match pipeline.add_term(&term, None).await {
+            Ok(report) if report.has_failures() => {
+                let reason = report
+                    .notes
+                    .iter()
+                    .find_map(|note| note.error.as_deref())
+                    .unwrap_or_default();
+                ("Card failed", format!("'{term}': {reason}"))
+            }
+            Ok(report) if report.is_queued() => (
+                "Card queued",
+                format!("'{term}' will be added to {} on sync", pipeline.deck.name()),
+            ),
+            Ok(report) if !report.is_duplicate() => (
+                "Card added",
+                format!("'{term}' was added to {}", pipeline.deck.name()),
+            ),
+            Ok(_) => (
+                "Already known",
+                format!("'{term}' is already in {}", pipeline.deck.name()),
+            ),
+            Err(err) => {
+                eprintln!("Failed to add '{term}': {err:#}");
+                ("Card failed", format!("'{term}': {err}"))
+            }
+        };
+        println!("{summary}: {body}");
+        if !args.no_notify {
+            clipboard::notify(summary, &body).await;
+        }
+    }
 }
@@ -0,0 +1,244 @@
+use std::collections::BTreeMap;
+
+use ankiconnect_rs::builders::Query;
+use anyhow::Result;
+
+use crate::anki::{AnkiConnect, NoteInfo};
+use crate::duplicates::normalize_term;
+use crate::journal::Journal;
+use crate::pipeline::build_term_tag;
+use crate::queue::{NoteQueue, PendingNote};
+
+/// Retags notes made before term tags kept non-Latin letters, when their
+/// slug no longer matches [`build_term_tag`]. Queued notes are retagged too.
+/// Prints one line per change and returns how many notes could not be
+/// migrated because their term was not found in their fields.
+pub async fn migrate_tags(
+    client: &AnkiConnect,
+    queue: &NoteQueue,
+    journal: &Journal,
+    dry_run: bool,
+) -> Result<usize> {
+    client.check().await?;
+    let ids = client
+        .find_notes(&Query::custom("tag:auto-generated tag:term:*".to_string()))
+        .await?;
+    let notes = if ids.is_empty() {
+        Vec::new()
+    } else {
+        client.notes_info(&ids).await?
+    };
+
+    // Notes sharing an old and new tag are retagged in one request.
+    let mut renames: BTreeMap<(String, String), Vec<u64>> = BTreeMap::new();
+    let mut unresolved = 0;
+    for note in &notes {
+        for tag in note.tags.iter().filter(|tag| tag.starts_with("term:")) {
+            match plan_note(note, tag) {
+                Plan::Keep => {}
+                Plan::Rename(new) => {
+                    println!("Note {}: {tag} -> {new}", note.note_id);
+                    renames
+                        .entry((tag.clone(), new))
+                        .or_default()
+                        .push(note.note_id);
+                }
+                Plan::Unresolved => {
+                    eprintln!(
+                        "Note {}: could not find the term behind '{tag}'; retag it by hand.",
+                        note.note_id
+                    );
+                    unresolved += 1;
+                }
+            }
+        }
+    }
+
+    let renamed: usize = renames.values().map(Vec::len).sum();
+    if dry_run {
+        let requeued = retag_queued(&mut queue.load()?);
+        println!("Would retag {renamed} note(s) and {requeued} queued note(s).");
+        return Ok(unresolved);
+    }
+    for ((tag, new), ids) in &renames {
+        client.replace_tags(ids, tag, new).await?;
+        journal.retagged(ids, tag, new)?;
+    }
+    let requeued = queue.update(|pending| retag_queued(pending))?;
+    println!("Retagged {renamed} note(s) and {requeued} queued note(s).");
+    Ok(unresolved)
+}
+
+/// Gives queued notes the current term tag, returning how many changed.
+fn retag_queued(pending: &mut [PendingNote]) -> usize {
+    let mut requeued = 0;
+    for note in pending {
+        let new = build_term_tag(&note.term);
+        if new != note.term_tag {
+            for tag in &mut note.fields.tags {
+                if *tag == note.term_tag {
+                    tag.clone_from(&new);
+                }
+            }
+            note.term_tag = new;
+            requeued += 1;
+        }
+    }
+    requeued
+}
+
+enum Plan {
+    Keep,
+    Rename(String),
+    Unresolved,
+}
+
+/// Works out the term a note was made for by finding the candidate in its
+/// fields that produces its old slug.
+fn plan_note(note: &NoteInfo, tag: &str) -> Plan {
+    let mut fields: Vec<_> = note.fields.values().collect();
+    fields.sort_by_key(|field| field.order);
+    // Production notes show the translation, also in bold, on the front.
+    if note.tags.iter().any(|tag| tag == "production") {
+        fields.reverse();
+    }
+    let candidates: Vec<String> = fields
+        .iter()
+        .flat_map(|field| term_candidates(&field.value))
+        .collect();
+
+    if candidates
+        .iter()
+        .any(|candidate| build_term_tag(candidate) == tag)
+    {
+        return Plan::Keep;
+    }
+    match candidates
+        .iter()
+        .find(|candidate| legacy_term_tag(candidate) == tag)
+    {
+        Some(term) => Plan::Rename(build_term_tag(term)),
+        // ASCII terms slug the same under both schemes, so only notes whose
+        // old slug may have lost letters need a closer look.
+        None if tag == "term:term" || candidates.iter().any(|term| !term.is_ascii()) => {
+            Plan::Unresolved
+        }
+        None => Plan::Keep,
+    }
+}
+
+/// Texts in a generated field that may be the term: bold runs, where the
+/// vocabulary, simple and production templates put it, and cloze deletions.
+fn term_candidates(value: &str) -> Vec<String> {
+    let mut candidates = Vec::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("<b") {
+        let Some(open_end) = rest[start..].find('>') else {
+            break;
+        };
+        let inner = &rest[start + open_end + 1..];
+        let Some(close) = inner.find("</b>") else {
+            break;
+        };
+        candidates.push(normalize_term(&inner[..close]));
+        rest = &inner[close..];
+    }
+
+    let mut rest = value;
+    while let Some(start) = rest.find("{{c") {
+        let inner = &rest[start..];
+        let (Some(open), Some(close)) = (inner.find("::"), inner.find("}}")) else {
+            break;
+        };
+        if open < close {
+            let deletion = &inner[open + 2..close];
+            let text = deletion.split("::").next().unwrap_or(deletion);
+            candidates.push(normalize_term(text));
+        }
+        rest = &inner[close..];
+    }
+
+    candidates.retain(|candidate| !candidate.is_empty());
+    candidates
+}
+
+/// The tag earlier versions built, which kept only ASCII letters and digits.
+fn legacy_term_tag(term: &str) -> String {
+    let mut slug = String::with_capacity(term.len());
+    let mut last_was_sep = false;
+
+    for c in term.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+            last_was_sep = false;
+        } else {
+            if !last_was_sep && !slug.is_empty() {
+                slug.push('_');
+            }
+            last_was_sep = true;
+        }
+    }
+
+    if slug.ends_with('_') {
+        slug.pop();
+    }
+
+    if slug.is_empty() {
+        slug.push_str("term");
+    }
+
+    format!("term:{}", slug)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(tag: &str, front: &str, back: &str) -> NoteInfo {
+        serde_json::from_value(serde_json::json!({
+            "noteId": 1,
+            "tags": ["auto-generated", tag],
+            "fields": {
+                "Front": { "value": front, "order": 0 },
+                "Back": { "value": back, "order": 1 },
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn finds_the_term_behind_old_slugs() {
+        let vocabulary = note(
+            "term:term",
+            "<b style=\"font-size:1.4em;\">Привет</b><br><span>[prʲɪˈvʲet]</span>",
+            "<b>hello</b>",
+        );
+        assert!(
+            matches!(plan_note(&vocabulary, "term:term"), Plan::Rename(tag) if tag == "term:привет")
+        );
+
+        let mut production = note("term:term", "<b>Япония</b>", "<b style=\"x\">日本</b>");
+        production.tags.push("production".to_string());
+        assert!(
+            matches!(plan_note(&production, "term:term"), Plan::Rename(tag) if tag == "term:日本")
+        );
+
+        let production = note("term:caf", "<b>кафе</b>", "<b style=\"x\">café</b>");
+        assert!(
+            matches!(plan_note(&production, "term:caf"), Plan::Rename(tag) if tag == "term:café")
+        );
+
+        let cloze = note(
+            "term:taken_aback",
+            "I was {{c1::taken aback::удивлён}}.",
+            "",
+        );
+        assert!(matches!(plan_note(&cloze, "term:taken_aback"), Plan::Keep));
+        assert!(matches!(plan_note(&vocabulary, "term:привет"), Plan::Keep));
+
+        let simple = note("term:term", "<b>日本</b>", "");
+        assert!(matches!(plan_note(&simple, "term:term"), Plan::Rename(tag) if tag == "term:日本"));
+        let lost = note("term:term", "日本", "");
+        assert!(matches!(plan_note(&lost, "term:term"), Plan::Unresolved));
+    }
+}
@@ -0,0 +1,462 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{Cursor, Read},
+    path::Path,
+};
+
+use anyhow::{Context, Result, anyhow};
+
+use crate::card_template::ExampleSentence;
+use crate::enrichment::Enrichment;
+use crate::subtitles;
+
+const MIN_WORD_LEN: usize = 3;
+
+/// A word from the document that could become a card.
+pub struct Candidate {
+    pub lemma: String,
+    /// Occurrences of any surface form in the document.
+    pub count: usize,
+    pub frequency_rank: Option<usize>,
+    /// First sentence the word appeared in, highlighting the form used there.
+    pub example: ExampleSentence,
+}
+
+/// Loads a document as a list of sentences.
+///
+/// The format is picked by extension: `.epub`, `.srt`, `.vtt`, anything else
+/// is read as plain text.
+pub fn load_sentences(path: &Path) -> Result<Vec<String>> {
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "epub" => {
+            let data =
+                fs::read(path).with_context(|| format!("failed to read '{}'", path.display()))?;
+            Ok(read_epub(&data)?
+                .iter()
+                .flat_map(|chapter| split_sentences(chapter))
+                .collect())
+        }
+        "srt" | "vtt" => {
+            let raw = read_text(path)?;
+            Ok(subtitles::parse(&raw)
+                .into_iter()
+                .map(|cue| cue.text)
+                .collect())
+        }
+        _ => Ok(split_sentences(&read_text(path)?)),
+    }
+}
+
+fn read_text(path: &Path) -> Result<String> {
+    fs::read_to_string(path).with_context(|| format!("failed to read '{}'", path.display()))
+}
+
+/// Returns the text of every (X)HTML document in the book, in archive order.
+fn read_epub(data: &[u8]) -> Result<Vec<String>> {
+    let mut archive =
+        zip::ZipArchive::new(Cursor::new(data)).context("EPUB is not a valid zip archive")?;
+
+    let mut chapters = Vec::new();
+    for index in 0..archive.len() {
+        let mut file = archive
+            .by_index(index)
+            .context("failed to read EPUB entry")?;
+        let name = file.name().to_ascii_lowercase();
+        if !(name.ends_with(".xhtml") || name.ends_with(".html") || name.ends_with(".htm")) {
+            continue;
+        }
+
+        let mut raw = String::new();
+        file.read_to_string(&mut raw)
+            .with_context(|| format!("failed to read EPUB entry '{name}'"))?;
+        chapters.push(html_to_text(&raw));
+    }
+
+    if chapters.is_empty() {
+        return Err(anyhow!("EPUB contains no readable chapters"));
+    }
+    Ok(chapters)
+}
+
+/// Strips tags, keeping block boundaries as blank lines so sentences don't merge.
+fn html_to_text(html: &str) -> String {
+    let body = html
+        .find("<body")
+        .map(|start| &html[start..])
+        .unwrap_or(html);
+
+    let mut text = String::with_capacity(body.len());
+    let mut rest = body;
+    while let Some(open) = rest.find('<') {
+        text.push_str(&rest[..open]);
+        let Some(close) = rest[open..].find('>') else {
+            break;
+        };
+        let tag = rest[open + 1..open + close].trim_start_matches('/');
+        let name = tag
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if matches!(
+            name.as_str(),
+            "p" | "div" | "br" | "li" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "tr"
+        ) {
+            text.push_str("\n\n");
+        }
+        rest = &rest[open + close + 1..];
+    }
+    text.push_str(rest);
+
+    decode_entities(&text)
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        decoded.push_str(&rest[..amp]);
+        let after = &rest[amp..];
+        let Some(semi) = after.find(';').filter(|&semi| semi <= 10) else {
+            decoded.push('&');
+            rest = &after[1..];
+            continue;
+        };
+
+        let entity = &after[1..semi];
+        let replacement = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => entity
+                .strip_prefix("#x")
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                .and_then(char::from_u32),
+        };
+
+        match replacement {
+            Some(c) => {
+                decoded.push(c);
+                rest = &after[semi + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &after[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Splits prose into sentences on terminal punctuation and blank lines.
+fn split_sentences(text: &str) -> Vec<String> {
+    let mut sentences = Vec::new();
+
+    for paragraph in text.split("\n\n") {
+        let paragraph = paragraph.split_whitespace().collect::<Vec<_>>().join(" ");
+        let mut current = String::new();
+        let mut chars = paragraph.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            current.push(c);
+            let terminal = matches!(c, '.' | '!' | '?' | '…');
+            if terminal && chars.peek().is_none_or(|next| next.is_whitespace()) {
+                push_sentence(&mut sentences, &current);
+                current.clear();
+            }
+        }
+        push_sentence(&mut sentences, &current);
+    }
+
+    sentences
+}
+
+fn push_sentence(sentences: &mut Vec<String>, sentence: &str) {
+    let sentence = sentence.trim();
+    if sentence.chars().any(char::is_alphabetic) {
+        sentences.push(sentence.to_string());
+    }
+}
+
+/// Yields each word in the sentence as written, keeping inner apostrophes
+/// and hyphens ("don't", "well-known").
+fn words(sentence: &str) -> Vec<&str> {
+    sentence
+        .split(|c: char| !(c.is_alphabetic() || c == '\'' || c == '’' || c == '-'))
+        .map(|word| word.trim_matches(|c: char| !c.is_alphabetic()))
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// Reduces an inflected English word form to a dictionary form.
+///
+/// Suffix rules that could mangle a word ("-ing", "-ed") are only applied
+/// when the frequency list confirms the result is a real word.
+fn lemmatize(word: &str, enrichment: &Enrichment) -> String {
+    let word = word
+        .trim_end_matches("'s")
+        .trim_end_matches("’s")
+        .to_lowercase();
+    if enrichment.has_frequency_list() && enrichment.frequency_rank(&word).is_some() {
+        return word;
+    }
+
+    let mut candidates = Vec::new();
+    if let Some(stem) = word.strip_suffix("ies") {
+        candidates.push(format!("{stem}y"));
+    }
+    if let Some(stem) = word.strip_suffix("es") {
+        candidates.push(stem.to_string());
+    }
+    if let Some(stem) = word.strip_suffix('s')
+        && !word.ends_with("ss")
+    {
+        candidates.push(stem.to_string());
+    }
+    for suffix in ["ing", "ed"] {
+        if let Some(stem) = word.strip_suffix(suffix) {
+            candidates.push(stem.to_string());
+            candidates.push(format!("{stem}e"));
+            if let Some(undoubled) = undouble(stem) {
+                candidates.push(undoubled);
+            }
+            if suffix == "ed"
+                && let Some(stem) = stem.strip_suffix('i')
+            {
+                candidates.push(format!("{stem}y"));
+            }
+        }
+    }
+
+    if enrichment.has_frequency_list() {
+        return candidates
+            .into_iter()
+            .filter(|candidate| candidate.chars().count() >= MIN_WORD_LEN)
+            .find(|candidate| enrichment.frequency_rank(candidate).is_some())
+            .unwrap_or(word);
+    }
+
+    // Without a word list, only undo plural forms that are safe to guess.
+    if let Some(stem) = word.strip_suffix("ies")
+        && stem.chars().count() >= 2
+    {
+        return format!("{stem}y");
+    }
+    // Sibilant plurals ("glasses", "boxes") can't be undone without the list.
+    let sibilant_plural = ["ses", "xes", "zes", "ches", "shes"]
+        .iter()
+        .any(|suffix| word.ends_with(suffix));
+    if word.ends_with('s')
+        && !word.ends_with("ss")
+        && !word.ends_with("us")
+        && !sibilant_plural
+        && word.len() > 4
+    {
+        return word[..word.len() - 1].to_string();
+    }
+    word
+}
+
+/// "stopp" -> "stop", "runn" -> "run".
+fn undouble(stem: &str) -> Option<String> {
+    let mut chars = stem.chars().rev();
+    let last = chars.next()?;
+    (chars.next() == Some(last) && !"aeiouls".contains(last))
+        .then(|| stem[..stem.len() - last.len_utf8()].to_string())
+}
+
+/// Groups the document's words by lemma and ranks them for mining.
+///
+/// Words that occur more often in the document come first; ties go to words
+/// that are more common in the language overall, and words missing from the
+/// frequency list (names, typos) sink to the end. Words only ever seen
+/// capitalised are treated as proper nouns and dropped.
+pub fn extract_candidates(sentences: &[String], enrichment: &Enrichment) -> Vec<Candidate> {
+    struct Entry {
+        count: usize,
+        lowercase_seen: bool,
+        sentence: usize,
+        surface: String,
+    }
+
+    let mut entries: HashMap<String, Entry> = HashMap::new();
+    let mut order = Vec::new();
+
+    for (sentence_index, sentence) in sentences.iter().enumerate() {
+        for word in words(sentence) {
+            if word.chars().count() < MIN_WORD_LEN {
+                continue;
+            }
+            let lemma = lemmatize(word, enrichment);
+            let lowercase = !word.starts_with(char::is_uppercase);
+
+            let entry = entries.entry(lemma.clone()).or_insert_with(|| {
+                order.push(lemma.clone());
+                Entry {
+                    count: 0,
+                    lowercase_seen: false,
+                    sentence: sentence_index,
+                    surface: word.to_string(),
+                }
+            });
+            entry.count += 1;
+            if lowercase && !entry.lowercase_seen {
+                // Prefer an example where the word isn't sentence-initial or a name.
+                entry.lowercase_seen = true;
+                entry.sentence = sentence_index;
+                entry.surface = word.to_string();
+            }
+        }
+    }
+
+    let mut candidates: Vec<Candidate> = order
+        .into_iter()
+        .filter_map(|lemma| {
+            let entry = entries.remove(&lemma)?;
+            entry.lowercase_seen.then(|| Candidate {
+                frequency_rank: enrichment.frequency_rank(&lemma),
+                lemma,
+                count: entry.count,
+                example: ExampleSentence {
+                    sentence: sentences[entry.sentence].clone(),
+                    highlight: entry.surface,
+                    translation: String::new(),
+                },
+            })
+        })
+        .collect();
+
+    candidates.sort_by_key(|candidate| {
+        (
+            std::cmp::Reverse(candidate.count),
+            candidate.frequency_rank.unwrap_or(usize::MAX),
+        )
+    });
+    candidates
+}
+
+/// Parses a selection like `1,3,5-7` or `all` into zero-based indexes.
+pub fn parse_selection(input: &str, available: usize) -> Result<Vec<usize>> {
+    let input = input.trim();
+    if input.eq_ignore_ascii_case("all") {
+        return Ok((0..available).collect());
+    }
+
+    let mut selected = Vec::new();
+    for part in input.split([',', ' ']).filter(|part| !part.is_empty()) {
+        let (from, to) = match part.split_once('-') {
+            Some((from, to)) => (from.trim().parse::<usize>()?, to.trim().parse::<usize>()?),
+            None => {
+                let number = part.parse::<usize>()?;
+                (number, number)
+            }
+        };
+
+        if from == 0 || to > available || from > to {
+            return Err(anyhow!("Selection '{part}' is out of range 1-{available}"));
+        }
+        for number in from..=to {
+            if !selected.contains(&(number - 1)) {
+                selected.push(number - 1);
+            }
+        }
+    }
+
+    Ok(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_sentences_and_words() {
+        let sentences =
+            split_sentences("She stopped. Was he\ntaken aback?\n\nWell-known words don't vanish");
+        assert_eq!(
+            sentences,
+            vec![
+                "She stopped.",
+                "Was he taken aback?",
+                "Well-known words don't vanish"
+            ]
+        );
+        assert_eq!(
+            words(&sentences[2]),
+            vec!["Well-known", "words", "don't", "vanish"]
+        );
+    }
+
+    #[test]
+    fn lemmatizes_with_and_without_frequency_list() {
+        let plain = Enrichment::default();
+        assert_eq!(lemmatize("Cities", &plain), "city");
+        assert_eq!(lemmatize("glasses", &plain), "glasses");
+        assert_eq!(lemmatize("stopped", &plain), "stopped");
+
+        let dir = tempfile::tempdir().unwrap();
+        let list = dir.path().join("freq.txt");
+        fs::write(&list, "stop\nlove\nrun\nbox\n").unwrap();
+        let enrichment = Enrichment::load(Some(&list), None).unwrap();
+        assert_eq!(lemmatize("stopped", &enrichment), "stop");
+        assert_eq!(lemmatize("loving", &enrichment), "love");
+        assert_eq!(lemmatize("running", &enrichment), "run");
+        assert_eq!(lemmatize("boxes", &enrichment), "box");
+    }
+
+    #[test]
+    fn extracts_candidates_with_document_sentence() {
+        let sentences = vec![
+            "Alice stopped the car.".to_string(),
+            "Then she stops again and again.".to_string(),
+        ];
+        let candidates = extract_candidates(&sentences, &Enrichment::default());
+
+        let lemmas: Vec<&str> = candidates.iter().map(|c| c.lemma.as_str()).collect();
+        assert_eq!(lemmas[0], "again");
+        assert!(!lemmas.contains(&"alice"));
+
+        let car = candidates.iter().find(|c| c.lemma == "car").unwrap();
+        assert_eq!(car.example.sentence, "Alice stopped the car.");
+        assert_eq!(car.example.highlight, "car");
+    }
+
+    #[test]
+    fn reads_text_from_epub_chapters() {
+        use std::io::Write;
+
+        let mut buffer = Cursor::new(Vec::new());
+        {
+            let mut zip = zip::ZipWriter::new(&mut buffer);
+            let options = zip::write::SimpleFileOptions::default();
+            zip.start_file("mimetype", options).unwrap();
+            zip.write_all(b"application/epub+zip").unwrap();
+            zip.start_file("OEBPS/ch1.xhtml", options).unwrap();
+            zip.write_all(b"<html><head><title>T</title></head><body><p>Caf&#233; &amp; tea.</p><p>He&#39;s late</p></body></html>").unwrap();
+            zip.finish().unwrap();
+        }
+
+        let chapters = read_epub(buffer.get_ref()).unwrap();
+        let sentences: Vec<String> = chapters.iter().flat_map(|c| split_sentences(c)).collect();
+        assert_eq!(sentences, vec!["Café & tea.", "He's late"]);
+    }
+
+    #[test]
+    fn parses_selection_ranges() {
+        assert_eq!(parse_selection("1,3-4", 5).unwrap(), vec![0, 2, 3]);
+        assert_eq!(parse_selection("all", 2).unwrap(), vec![0, 1]);
+        assert!(parse_selection("6", 5).is_err());
+        assert!(parse_selection("", 5).unwrap().is_empty());
+    }
+}
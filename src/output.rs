@@ -0,0 +1,112 @@
+use clap::ValueEnum;
+use serde_json::json;
+
+use crate::error::ErrorKind;
+use crate::pipeline::{INCOMPLETE_TAG, TermReport};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable lines
+    #[default]
+    Text,
+    /// One JSON record per term (JSON Lines)
+    Json,
+}
+
+/// Prints the result of one term, added or skipped. `scope` describes where
+/// duplicates were looked for, e.g. "deck 'English'".
+pub fn print_report(format: OutputFormat, scope: &str, report: &TermReport) {
+    match format {
+        OutputFormat::Text => {
+            let degraded = report.degraded_fields();
+            if !degraded.is_empty()
+                && report
+                    .notes
+                    .iter()
+                    .any(|note| note.note_id.is_some() || note.queued)
+            {
+                eprintln!(
+                    "Warning: card for '{}' is incomplete ({}); tagged {INCOMPLETE_TAG}.",
+                    report.term,
+                    degraded.join(", ")
+                );
+            }
+            for warning in &report.warnings {
+                eprintln!("Warning for '{}': {warning}", report.term);
+            }
+            for note in &report.notes {
+                match note.note_id {
+                    Some(id) => println!("Added note with ID: {id}"),
+                    None if let Some(error) = &note.error => eprintln!(
+                        "Failed to add {} for '{}': {error}",
+                        note.direction.note_label().to_lowercase(),
+                        report.term
+                    ),
+                    None if note.queued => println!(
+                        "{} for term '{}' queued until Anki is reachable.",
+                        note.direction.note_label(),
+                        report.term
+                    ),
+                    None => println!(
+                        "{} for term '{}' already exists in {}; skipping.",
+                        note.direction.note_label(),
+                        report.term,
+                        scope
+                    ),
+                }
+            }
+        }
+        OutputFormat::Json => {
+            let mut record = serde_json::to_value(report).unwrap_or_default();
+            record["status"] = json!(if report.has_failures() {
+                "failed"
+            } else if report.is_queued() {
+                "queued"
+            } else if report.is_duplicate() {
+                "duplicate"
+            } else {
+                "added"
+            });
+            println!("{record}");
+        }
+    }
+}
+
+/// Prints a term that could not be added.
+pub fn print_failure(format: OutputFormat, term: &str, err: &anyhow::Error) {
+    match format {
+        OutputFormat::Text => eprintln!("Failed to add '{term}': {err:#}"),
+        OutputFormat::Json => println!("{}", failure_record(term, err)),
+    }
+}
+
+fn failure_record(term: &str, err: &anyhow::Error) -> serde_json::Value {
+    json!({
+        "term": term,
+        "status": "failed",
+        "error": format!("{err:#}"),
+        "error_kind": ErrorKind::of(err).map(ErrorKind::name),
+        "notes": [],
+        "providers": [],
+        "sources": [],
+        "warnings": [],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failure_record_carries_error_kind() {
+        let err = anyhow::Error::new(ErrorKind::ProviderFailed).context("No lookup succeeded");
+        let record = failure_record("aback", &err);
+
+        assert_eq!(record["status"], "failed");
+        assert_eq!(record["error_kind"], "provider_failed");
+        assert_eq!(
+            record["error"],
+            "No lookup succeeded: lookup provider failed"
+        );
+    }
+}
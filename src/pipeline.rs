@@ -0,0 +1,994 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use ankiconnect_rs::{Deck, Model, Note, NoteBuilder};
+use anyhow::Result;
+use clap::ValueEnum;
+use icu_normalizer::ComposingNormalizerBorrowed;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::anki::{AnkiConnect, get_model_field, is_duplicate_error, store_media};
+use crate::card_template::{
+    CardFields, CardTemplate, ClozeCard, ExampleSentence, FieldSource, ProductionCard, SimpleCard,
+    VocabularyCard, render_examples,
+};
+use crate::clips::Clip;
+use crate::duplicates::{DuplicateScope, Duplicates, normalize_term};
+use crate::enrichment::Enrichment;
+use crate::error::ErrorKind;
+use crate::images::ImageLookup;
+use crate::journal::Journal;
+use crate::queue::{MediaFile, MediaKind, NoteQueue, PendingNote, is_offline};
+use crate::vocab_service::{LookupOptions, build_vocabulary_card};
+
+/// Terms looked up and submitted per `addNotes` request in bulk mode.
+const BULK_CHUNK: usize = 50;
+
+/// Tag for notes built while a provider was failing, so they can be redone.
+pub const INCOMPLETE_TAG: &str = "notaforge:incomplete";
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum TemplateKind {
+    Vocabulary,
+    Simple,
+    Cloze,
+}
+
+impl TemplateKind {
+    /// Names of the model fields that receive the rendered front and back.
+    pub fn field_names(self) -> (&'static str, &'static str) {
+        match self {
+            TemplateKind::Vocabulary | TemplateKind::Simple => ("Front", "Back"),
+            TemplateKind::Cloze => ("Text", "Back Extra"),
+        }
+    }
+}
+
+/// Which side of the vocabulary pair a note drills.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CardDirection {
+    /// Term on the front, translation on the back.
+    Recognition,
+    /// Translation and a blanked example on the front, term on the back.
+    Production,
+}
+
+impl CardDirection {
+    pub fn note_label(self) -> &'static str {
+        match self {
+            CardDirection::Recognition => "Note",
+            CardDirection::Production => "Production note",
+        }
+    }
+}
+
+/// What happened to one note of a term.
+#[derive(Debug, Serialize)]
+pub struct NoteOutcome {
+    pub direction: CardDirection,
+    /// `None` when the deck already had the note or it was queued.
+    pub note_id: Option<u64>,
+    /// Anki was unreachable, so the note waits in the queue for `sync`.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub queued: bool,
+    /// Why Anki refused the note.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Fields sent to Anki; absent for notes skipped before the lookup.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fields: Option<CardFields>,
+}
+
+impl NoteOutcome {
+    /// The deck already had the note.
+    fn existing(direction: CardDirection) -> Self {
+        Self {
+            direction,
+            note_id: None,
+            queued: false,
+            error: None,
+            fields: None,
+        }
+    }
+
+    fn added(direction: CardDirection, note_id: Option<u64>, fields: CardFields) -> Self {
+        Self {
+            direction,
+            note_id,
+            queued: false,
+            error: None,
+            fields: Some(fields),
+        }
+    }
+
+    fn failed(direction: CardDirection, error: &str) -> Self {
+        Self {
+            direction,
+            note_id: None,
+            queued: false,
+            error: Some(error.to_string()),
+            fields: None,
+        }
+    }
+}
+
+/// Everything that happened while adding one term.
+#[derive(Debug, Serialize)]
+pub struct TermReport {
+    pub term: String,
+    pub notes: Vec<NoteOutcome>,
+    pub providers: Vec<String>,
+    /// Provenance of each looked-up field.
+    pub sources: Vec<FieldSource>,
+    pub warnings: Vec<String>,
+}
+
+impl TermReport {
+    fn new(term: &str) -> Self {
+        Self {
+            term: term.to_string(),
+            notes: Vec::new(),
+            providers: Vec::new(),
+            sources: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Fields that fell back or stayed empty.
+    pub fn degraded_fields(&self) -> Vec<&str> {
+        self.sources
+            .iter()
+            .filter(|source| source.status.is_degraded())
+            .map(|source| source.field.as_str())
+            .collect()
+    }
+
+    /// True when every note already existed, so nothing was added.
+    pub fn is_duplicate(&self) -> bool {
+        self.notes
+            .iter()
+            .all(|note| note.note_id.is_none() && !note.queued && note.error.is_none())
+    }
+
+    /// True when Anki refused at least one note.
+    pub fn has_failures(&self) -> bool {
+        self.notes.iter().any(|note| note.error.is_some())
+    }
+
+    pub fn is_queued(&self) -> bool {
+        self.notes.iter().any(|note| note.queued)
+    }
+}
+
+/// Everything needed to turn a term into notes in one deck.
+pub struct Pipeline {
+    pub client: AnkiConnect,
+    pub http: Client,
+    /// With `model`, a stand-in built from names while offline and in the
+    /// proxy, whose notes bring their own deck and model.
+    pub deck: Deck,
+    pub model: Model,
+    pub template: TemplateKind,
+    pub reverse: bool,
+    pub lookup: LookupOptions,
+    pub enrichment: Enrichment,
+    pub images: ImageLookup,
+    /// Model field receiving the `<img>`; may be the template's front or back.
+    pub image_field: String,
+    /// Model field receiving the `[sound:]` tag of subtitle clips.
+    pub audio_field: String,
+    pub extra_tags: Vec<String>,
+    /// Refuse to add cards with degraded fields instead of tagging them.
+    pub strict: bool,
+    /// Which existing notes count as duplicates.
+    pub duplicates: Duplicates,
+    /// Looked-up cards by lowercased term, so a preview followed by an add
+    /// (or a repeated request in `serve` mode) does the lookups once.
+    pub card_cache: Mutex<HashMap<String, VocabularyCard>>,
+    /// Where notes go while AnkiConnect is unreachable; locked so concurrent
+    /// `serve` requests don't lose each other's notes.
+    pub queue: tokio::sync::Mutex<NoteQueue>,
+    /// Notes this run created, for `undo`.
+    pub journal: Journal,
+    /// Set when AnkiConnect was unreachable at startup or stopped answering
+    /// mid-run; `deck` and `model` are then stand-ins built from names.
+    pub offline: AtomicBool,
+}
+
+impl Pipeline {
+    /// Fails early when the model lacks a field the pipeline writes to.
+    pub fn check_fields(&self) -> Result<()> {
+        let (front_name, back_name) = self.template.field_names();
+        get_model_field(&self.model, front_name)?;
+        get_model_field(&self.model, back_name)?;
+        if self.images.is_enabled() {
+            get_model_field(&self.model, &self.image_field)?;
+        }
+        Ok(())
+    }
+
+    /// Looks the term up and adds every note for it that the deck lacks.
+    ///
+    /// `context` replaces the looked-up examples, see [`build_vocabulary_card`].
+    pub async fn add_term(
+        &self,
+        term: &str,
+        context: Option<ExampleSentence>,
+    ) -> Result<TermReport> {
+        self.add_term_with_clip(term, context, None).await
+    }
+
+    /// Like [`Pipeline::add_term`], attaching a subtitle clip's audio and
+    /// screenshot; the screenshot replaces the image lookup.
+    pub async fn add_term_with_clip(
+        &self,
+        term: &str,
+        context: Option<ExampleSentence>,
+        clip: Option<&Clip>,
+    ) -> Result<TermReport> {
+        let term_tag = build_term_tag(term);
+        let mut report = TermReport::new(term);
+        let handmade = !self.offline.load(Ordering::Relaxed)
+            && !self
+                .duplicates
+                .find_handmade(&self.client, self.deck.name(), &[term])
+                .await?
+                .is_empty();
+        let mut pending = Vec::new();
+        for direction in self.directions() {
+            if handmade || self.has_note(&term_tag, direction).await? {
+                report.notes.push(NoteOutcome::existing(direction));
+            } else {
+                pending.push(direction);
+            }
+        }
+
+        if pending.is_empty() {
+            return Ok(report);
+        }
+        let notes = self
+            .build_notes(term, context, clip, &pending, &mut report)
+            .await?;
+
+        // Media shared by both directions is uploaded once.
+        let mut stored = HashMap::new();
+        for note in notes {
+            let direction = note.direction;
+            let submitted = if self.offline.load(Ordering::Relaxed) {
+                None
+            } else {
+                match submit_note(
+                    &self.client,
+                    &self.deck,
+                    &self.model,
+                    &note,
+                    self.duplicates.scope,
+                    &mut stored,
+                    &mut report.warnings,
+                )
+                .await
+                {
+                    Ok(submitted) => Some(submitted),
+                    Err(err) if is_offline(&err) => {
+                        report.warnings.push(format!("{err:#}"));
+                        self.offline.store(true, Ordering::Relaxed);
+                        None
+                    }
+                    Err(err) => return Err(err),
+                }
+            };
+
+            let outcome = match submitted {
+                Some((note_id, fields)) => {
+                    self.journal_created(note_id, &mut report.warnings);
+                    NoteOutcome::added(direction, note_id, fields)
+                }
+                None => self.enqueue(note).await?,
+            };
+            report.notes.push(outcome);
+        }
+
+        Ok(report)
+    }
+
+    /// Adds many terms with few AnkiConnect round trips, calling `on_result`
+    /// for each term in order.
+    ///
+    /// The deck's term tags are fetched once up front instead of a duplicate
+    /// query per note; each chunk of terms is then looked up, pre-validated
+    /// with `canAddNotesWithErrorDetail` and added with one `addNotes`.
+    pub async fn add_terms_bulk(
+        &self,
+        terms: Vec<(String, Option<ExampleSentence>)>,
+        mut on_result: impl FnMut(&str, Result<TermReport>),
+    ) -> Result<()> {
+        let mut existing = self.existing_notes().await?;
+
+        for chunk in terms.chunks(BULK_CHUNK) {
+            let handmade = if self.offline.load(Ordering::Relaxed) {
+                HashSet::new()
+            } else {
+                let terms: Vec<&str> = chunk.iter().map(|(term, _)| term.as_str()).collect();
+                self.duplicates
+                    .find_handmade(&self.client, self.deck.name(), &terms)
+                    .await?
+            };
+
+            let mut results = Vec::with_capacity(chunk.len());
+            let mut notes: Vec<(usize, PendingNote)> = Vec::new();
+            for (term, context) in chunk {
+                let term_tag = build_term_tag(term);
+                let mut report = TermReport::new(term);
+                let mut pending = Vec::new();
+                for direction in self.directions() {
+                    if handmade.contains(&normalize_term(term))
+                        || existing.contains(&(term_tag.clone(), direction))
+                    {
+                        report.notes.push(NoteOutcome::existing(direction));
+                    } else {
+                        pending.push(direction);
+                    }
+                }
+
+                if !pending.is_empty() {
+                    match self
+                        .build_notes(term, context.clone(), None, &pending, &mut report)
+                        .await
+                    {
+                        Ok(built) => {
+                            for note in built {
+                                // Repeats later in the list count as existing.
+                                existing.insert((term_tag.clone(), note.direction));
+                                notes.push((results.len(), note));
+                            }
+                        }
+                        Err(err) => {
+                            results.push(Err(err));
+                            continue;
+                        }
+                    }
+                }
+                results.push(Ok(report));
+            }
+
+            let outcomes = if self.offline.load(Ordering::Relaxed) {
+                Err(anyhow::Error::new(ErrorKind::AnkiUnreachable))
+            } else {
+                self.submit_bulk(&notes, &mut results).await
+            };
+            let outcomes = match outcomes {
+                Ok(outcomes) => outcomes,
+                Err(err) if is_offline(&err) => {
+                    if !self.offline.swap(true, Ordering::Relaxed) {
+                        eprintln!("Warning: {err}; queueing the remaining notes.");
+                    }
+                    let mut queued = Vec::with_capacity(notes.len());
+                    for (_, note) in &notes {
+                        queued.push(self.enqueue(note.clone()).await?);
+                    }
+                    queued
+                }
+                Err(err) => return Err(err),
+            };
+
+            for ((index, _), outcome) in notes.iter().zip(outcomes) {
+                if let Ok(report) = &mut results[*index] {
+                    report.notes.push(outcome);
+                }
+            }
+            for ((term, _), result) in chunk.iter().zip(results) {
+                on_result(term, result);
+            }
+        }
+        Ok(())
+    }
+
+    /// Uploads media and adds a chunk of notes, returning one outcome per
+    /// note. Notes Anki would refuse are reported without being sent.
+    async fn submit_bulk(
+        &self,
+        notes: &[(usize, PendingNote)],
+        results: &mut [Result<TermReport>],
+    ) -> Result<Vec<NoteOutcome>> {
+        let mut stored = HashMap::new();
+        let mut outcomes = Vec::with_capacity(notes.len());
+        let mut prepared = Vec::new();
+        for (position, (index, note)) in notes.iter().enumerate() {
+            let mut warnings = Vec::new();
+            let result =
+                prepare_note(&self.client, &self.model, note, &mut stored, &mut warnings).await;
+            if let Ok(report) = &mut results[*index] {
+                report.warnings.extend(warnings);
+            }
+            match result {
+                Ok((built, fields)) => {
+                    prepared.push((position, built));
+                    outcomes.push(NoteOutcome::added(note.direction, None, fields));
+                }
+                Err(err) if ErrorKind::of(&err).is_some() => return Err(err),
+                Err(err) => outcomes.push(NoteOutcome::failed(note.direction, &err.to_string())),
+            }
+        }
+
+        let built: Vec<&Note> = prepared.iter().map(|(_, note)| note).collect();
+        let verdicts = if built.is_empty() {
+            Vec::new()
+        } else {
+            self.client
+                .can_add_notes(&self.deck, &built, self.duplicates.scope)
+                .await?
+        };
+        let mut addable = Vec::new();
+        for ((position, note), verdict) in prepared.iter().zip(verdicts) {
+            match verdict {
+                Ok(()) => addable.push((*position, note)),
+                Err(error) if is_duplicate_error(&error) => {
+                    outcomes[*position] = NoteOutcome::existing(outcomes[*position].direction);
+                }
+                Err(error) => {
+                    outcomes[*position] =
+                        NoteOutcome::failed(outcomes[*position].direction, &error);
+                }
+            }
+        }
+
+        if addable.is_empty() {
+            return Ok(outcomes);
+        }
+        let to_add: Vec<&Note> = addable.iter().map(|(_, note)| *note).collect();
+        // `Ok(None)` is a duplicate, `Err` the reason Anki refused the note.
+        let added: Vec<Result<Option<u64>, String>> = match self
+            .client
+            .add_notes(&self.deck, &to_add, self.duplicates.scope)
+            .await
+        {
+            Ok(ids) => ids
+                .into_iter()
+                .map(|id| {
+                    id.map(Some)
+                        .ok_or_else(|| "Anki refused the note".to_string())
+                })
+                .collect(),
+            Err(err) if ErrorKind::of(&err).is_some() => return Err(err),
+            // Newer AnkiConnect versions fail the whole request when one note
+            // is refused, so fall back to adding them one at a time.
+            Err(_) => {
+                let mut added = Vec::with_capacity(to_add.len());
+                for note in &to_add {
+                    match self
+                        .client
+                        .add_note(&self.deck, note, self.duplicates.scope)
+                        .await
+                    {
+                        Ok(id) => added.push(Ok(id)),
+                        Err(err) if ErrorKind::of(&err).is_some() => {
+                            // The caller queues the whole chunk, so keep
+                            // what was added so far undoable.
+                            for ((position, _), id) in addable.iter().zip(&added) {
+                                if let (Ok(Some(id)), Ok(report)) =
+                                    (id, &mut results[notes[*position].0])
+                                {
+                                    self.journal_created(Some(*id), &mut report.warnings);
+                                }
+                            }
+                            return Err(err);
+                        }
+                        Err(err) => added.push(Err(err.to_string())),
+                    }
+                }
+                added
+            }
+        };
+        for ((position, _), id) in addable.iter().zip(added) {
+            let outcome = &mut outcomes[*position];
+            match id {
+                Ok(Some(id)) => {
+                    outcome.note_id = Some(id);
+                    if let Ok(report) = &mut results[notes[*position].0] {
+                        self.journal_created(Some(id), &mut report.warnings);
+                    }
+                }
+                Ok(None) => *outcome = NoteOutcome::existing(outcome.direction),
+                Err(error) => *outcome = NoteOutcome::failed(outcome.direction, &error),
+            }
+        }
+        Ok(outcomes)
+    }
+
+    /// Term tags and directions of the generated notes already within the
+    /// duplicate scope, fetched with one search.
+    async fn existing_notes(&self) -> Result<HashSet<(String, CardDirection)>> {
+        let query = self.duplicates.generated_query(self.deck.name());
+        let ids = self.client.find_notes(&query).await?;
+        if ids.is_empty() {
+            return Ok(HashSet::new());
+        }
+
+        let infos = self.client.notes_info(&ids).await?;
+        Ok(infos
+            .into_iter()
+            .flat_map(|info| note_keys(info.tags))
+            .collect())
+    }
+
+    /// Looks the term up and renders a note per direction, with the media to
+    /// upload alongside.
+    async fn build_notes(
+        &self,
+        term: &str,
+        context: Option<ExampleSentence>,
+        clip: Option<&Clip>,
+        directions: &[CardDirection],
+        report: &mut TermReport,
+    ) -> Result<Vec<PendingNote>> {
+        let (front_name, back_name) = self.template.field_names();
+        get_model_field(&self.model, front_name)?;
+        get_model_field(&self.model, back_name)?;
+        let term_tag = build_term_tag(term);
+
+        let vocabulary_card = self.lookup(term, context).await?;
+        report.providers = vocabulary_card.providers();
+        report.sources = vocabulary_card.sources.clone();
+        report.warnings = vocabulary_card.warnings.clone();
+
+        let degraded = vocabulary_card.degraded_fields();
+        if self.strict && !degraded.is_empty() {
+            return Err(
+                anyhow::Error::new(ErrorKind::ProviderFailed).context(format!(
+                    "Card for '{term}' is incomplete ({}); not adding it in strict mode",
+                    degraded.join(", ")
+                )),
+            );
+        }
+
+        let media = match clip {
+            Some(clip) => {
+                let stem = format!(
+                    "notaforge_{}_{}",
+                    term_tag.trim_start_matches("term:"),
+                    clip.start.as_millis()
+                );
+                vec![
+                    MediaFile::new(
+                        &self.audio_field,
+                        format!("{stem}.mp3"),
+                        MediaKind::Sound,
+                        &clip.audio,
+                    ),
+                    MediaFile::new(
+                        &self.image_field,
+                        format!("{stem}.jpg"),
+                        MediaKind::Image,
+                        &clip.screenshot,
+                    ),
+                ]
+            }
+            None => self
+                .find_image(term, &term_tag, &mut report.warnings)
+                .await
+                .into_iter()
+                .collect(),
+        };
+
+        Ok(directions
+            .iter()
+            .map(|&direction| PendingNote {
+                term: term.to_string(),
+                term_tag: term_tag.clone(),
+                direction,
+                deck: self.deck.name().to_string(),
+                model: self.model.name().to_string(),
+                front_field: front_name.to_string(),
+                back_field: back_name.to_string(),
+                fields: self.render(&vocabulary_card, direction),
+                media: media.clone(),
+                queued_at: 0,
+            })
+            .collect())
+    }
+
+    /// A journal that cannot be written must not fail a note Anki already has.
+    fn journal_created(&self, note_id: Option<u64>, warnings: &mut Vec<String>) {
+        if let Some(id) = note_id
+            && let Err(err) = self.journal.created(id)
+        {
+            warnings.push(format!("note {id} was not recorded for undo: {err:#}"));
+        }
+    }
+
+    async fn enqueue(&self, note: PendingNote) -> Result<NoteOutcome> {
+        let queue = self.queue.lock().await;
+        // Another request may have queued the same note meanwhile.
+        if queue.contains(&note.deck, &note.term_tag, note.direction)? {
+            return Ok(NoteOutcome::existing(note.direction));
+        }
+        let outcome = NoteOutcome {
+            direction: note.direction,
+            note_id: None,
+            queued: true,
+            error: None,
+            fields: Some(note.fields.clone()),
+        };
+        queue.push(note)?;
+        Ok(outcome)
+    }
+
+    /// Whether the deck already has the note, or, while Anki is unreachable,
+    /// whether it is already queued.
+    async fn has_note(&self, term_tag: &str, direction: CardDirection) -> Result<bool> {
+        if self.offline.load(Ordering::Relaxed) {
+            return self
+                .queue
+                .lock()
+                .await
+                .contains(self.deck.name(), term_tag, direction);
+        }
+        let duplicate_query = self.duplicates.query(self.deck.name(), term_tag, direction);
+        Ok(!self.client.find_cards(&duplicate_query).await?.is_empty())
+    }
+
+    /// Directions a term gets notes for.
+    pub fn directions(&self) -> Vec<CardDirection> {
+        let mut directions = vec![CardDirection::Recognition];
+        if self.reverse {
+            directions.push(CardDirection::Production);
+        }
+        directions
+    }
+
+    /// Builds and enriches the card for a term, reusing earlier lookups
+    /// unless the caller supplies its own example.
+    pub async fn lookup(
+        &self,
+        term: &str,
+        context: Option<ExampleSentence>,
+    ) -> Result<VocabularyCard> {
+        let key = term.trim().to_lowercase();
+        if context.is_none()
+            && let Some(card) = self.card_cache.lock().unwrap().get(&key)
+        {
+            return Ok(card.clone());
+        }
+
+        let cacheable = context.is_none();
+        let mut card = build_vocabulary_card(&self.http, term, context, &self.lookup).await?;
+        self.enrichment.apply(&mut card);
+
+        if cacheable {
+            self.card_cache.lock().unwrap().insert(key, card.clone());
+        }
+        Ok(card)
+    }
+
+    /// Renders the note fields and tags for one direction, without media.
+    pub fn render(&self, card: &VocabularyCard, direction: CardDirection) -> CardFields {
+        let mut fields = match direction {
+            CardDirection::Recognition => match self.template {
+                TemplateKind::Vocabulary => card.render(),
+                TemplateKind::Simple => render_simple_fields(card),
+                TemplateKind::Cloze => render_cloze_fields(card),
+            },
+            CardDirection::Production => render_production_fields(card),
+        };
+
+        let term_tag = build_term_tag(&card.term);
+        if !fields.tags.iter().any(|tag| tag == &term_tag) {
+            fields.tags.push(term_tag);
+        }
+        if !card.degraded_fields().is_empty() {
+            fields.tags.push(INCOMPLETE_TAG.to_string());
+        }
+
+        for tag in &self.extra_tags {
+            if !fields.tags.iter().any(|existing| existing == tag) {
+                fields.tags.push(tag.clone());
+            }
+        }
+
+        fields
+    }
+
+    /// Looks up a picture for the term, to be uploaded with the note.
+    ///
+    /// A missing picture shouldn't cost the whole card, so failures only warn.
+    async fn find_image(
+        &self,
+        term: &str,
+        term_tag: &str,
+        warnings: &mut Vec<String>,
+    ) -> Option<MediaFile> {
+        if !self.images.is_enabled() {
+            return None;
+        }
+
+        match self.images.find(&self.http, term).await {
+            Ok(Some(image)) => {
+                let filename = format!(
+                    "notaforge_{}.{}",
+                    term_tag.trim_start_matches("term:"),
+                    image.extension
+                );
+                Some(MediaFile::new(
+                    &self.image_field,
+                    filename,
+                    MediaKind::Image,
+                    &image.data,
+                ))
+            }
+            Ok(None) => None,
+            Err(err) => {
+                warnings.push(format!("image lookup: {err:#}"));
+                None
+            }
+        }
+    }
+}
+
+fn render_simple_fields(card: &VocabularyCard) -> CardFields {
+    let mut tags = card.extra_tags.clone();
+    if !card.part_of_speech.is_empty() {
+        tags.push(card.part_of_speech.clone());
+    }
+
+    let translations = card.render_translations(|section| {
+        let synonyms_block = if section.synonyms.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "<div style=\"margin-top:0.6em; color:#5e84c1;\">{}</div>",
+                section.synonyms
+            )
+        };
+        format!(
+            concat!(
+                "<div style=\"font-size:1.2em;\">{translation}</div>",
+                "{synonyms}",
+                "<div style=\"margin-top:0.8em; color:#666;\">{usage}</div>",
+            ),
+            translation = section.heading,
+            synonyms = synonyms_block,
+            usage = section.usage,
+        )
+    });
+
+    SimpleCard {
+        front: format!("<b>{}</b>", card.term),
+        back: format!(
+            concat!(
+                "{translations}",
+                "<div style=\"margin-top:0.8em;\">{examples}</div>",
+                "<div style=\"margin-top:0.8em;\">{metadata}</div>",
+            ),
+            translations = translations,
+            examples = render_examples(&card.examples),
+            metadata = card.render_metadata(),
+        ),
+        tags,
+    }
+    .render()
+}
+
+fn render_cloze_fields(card: &VocabularyCard) -> CardFields {
+    let mut tags = card.extra_tags.clone();
+    if !card.part_of_speech.is_empty() {
+        tags.push(card.part_of_speech.clone());
+    }
+
+    ClozeCard {
+        term: card.term.clone(),
+        example: card.primary_example(),
+        hint: card.primary_translation().heading,
+        translation: card.translation_headings("<br>"),
+        definition: card.render_translations(|section| section.usage.clone()),
+        tags,
+    }
+    .render()
+}
+
+fn render_production_fields(card: &VocabularyCard) -> CardFields {
+    ProductionCard {
+        term: card.term.clone(),
+        pronunciation: card.pronunciation.clone(),
+        translation: card.translation_headings(" / "),
+        example: card.primary_example(),
+        tags: card.extra_tags.clone(),
+    }
+    .render()
+}
+
+/// Uploads a built note's media and adds it to `deck`, returning the new
+/// note ID (`None` if Anki refused a duplicate) and the fields as sent.
+pub async fn submit_note(
+    client: &AnkiConnect,
+    deck: &Deck,
+    model: &Model,
+    note: &PendingNote,
+    scope: DuplicateScope,
+    stored: &mut HashMap<String, String>,
+    warnings: &mut Vec<String>,
+) -> Result<(Option<u64>, CardFields)> {
+    let (built, fields) = prepare_note(client, model, note, stored, warnings).await?;
+    let note_id = client.add_note(deck, &built, scope).await?;
+    Ok((note_id, fields))
+}
+
+/// Uploads a built note's media and assembles the note for `model`.
+///
+/// `stored` maps media file names to the names Anki stored them under, so a
+/// file shared by several notes is uploaded once. A picture that fails to
+/// upload only costs a warning, as long as Anki is reachable.
+async fn prepare_note(
+    client: &AnkiConnect,
+    model: &Model,
+    note: &PendingNote,
+    stored: &mut HashMap<String, String>,
+    warnings: &mut Vec<String>,
+) -> Result<(Note, CardFields)> {
+    let mut fields = note.fields.clone();
+    let mut separate: Vec<(&str, String)> = Vec::new();
+    for media in &note.media {
+        let name = match stored.get(&media.filename) {
+            Some(name) => name.clone(),
+            None => match store_media(client, &media.filename, &media.data).await {
+                Ok(name) => {
+                    stored.insert(media.filename.clone(), name.clone());
+                    name
+                }
+                Err(err) if media.kind == MediaKind::Image && ErrorKind::of(&err).is_none() => {
+                    warnings.push(format!("image upload: {err:#}"));
+                    continue;
+                }
+                Err(err) => return Err(err),
+            },
+        };
+
+        let html = media.html(&name);
+        if media.field == note.front_field {
+            fields.front.push_str(&html);
+        } else if media.field == note.back_field {
+            fields.back.push_str(&html);
+        } else if let Some((_, value)) =
+            separate.iter_mut().find(|(field, _)| *field == media.field)
+        {
+            value.push_str(&html);
+        } else {
+            separate.push((&media.field, html));
+        }
+    }
+
+    let mut builder = NoteBuilder::new(model.clone())
+        .with_field_raw(get_model_field(model, &note.front_field)?, &fields.front)
+        .with_field_raw(get_model_field(model, &note.back_field)?, &fields.back);
+
+    for (field, value) in &separate {
+        builder = builder.with_field_raw(get_model_field(model, field)?, value);
+    }
+
+    for tag in &fields.tags {
+        builder = builder.with_tag(tag);
+    }
+
+    Ok((builder.build()?, fields))
+}
+
+/// The term tags of a generated note, each paired with the direction its
+/// `production` tag implies.
+fn note_keys(tags: Vec<String>) -> Vec<(String, CardDirection)> {
+    let direction = if tags.iter().any(|tag| tag == "production") {
+        CardDirection::Production
+    } else {
+        CardDirection::Recognition
+    };
+    tags.into_iter()
+        .filter(|tag| tag.starts_with("term:"))
+        .map(|tag| (tag, direction))
+        .collect()
+}
+
+/// The tag identifying a term's notes, e.g. `term:taken_aback`.
+///
+/// The term is NFC-normalized and lowercased, and runs of whitespace and
+/// punctuation become a single `_`; letters and marks of every script are
+/// kept, so "café" and "привет" get tags of their own. A term with nothing
+/// left, such as "?!", is identified by a stable hash instead.
+pub fn build_term_tag(term: &str) -> String {
+    let normalized = ComposingNormalizerBorrowed::new_nfc()
+        .normalize(term.trim())
+        .to_lowercase();
+    let mut slug = String::with_capacity(normalized.len());
+    let mut last_was_sep = false;
+
+    for c in normalized.chars() {
+        if is_separator(c) {
+            if !last_was_sep && !slug.is_empty() {
+                slug.push('_');
+            }
+            last_was_sep = true;
+        } else {
+            slug.push(c);
+            last_was_sep = false;
+        }
+    }
+
+    if slug.ends_with('_') {
+        slug.pop();
+    }
+
+    if slug.is_empty() {
+        // Slugs never start with `_`, so hashed tags cannot collide with them.
+        return format!("term:_{:016x}", fnv1a(normalized.as_bytes()));
+    }
+
+    format!("term:{}", slug)
+}
+
+/// Characters that cannot appear in a term slug: whitespace, controls, ASCII
+/// symbols and the common Unicode punctuation blocks.
+fn is_separator(c: char) -> bool {
+    if c.is_ascii() {
+        return !c.is_ascii_alphanumeric();
+    }
+    c.is_whitespace()
+        || c.is_control()
+        || matches!(c,
+            '\u{00a1}'..='\u{00bf}' // Latin-1 punctuation and symbols
+            | '\u{00d7}' | '\u{00f7}'
+            | '\u{2000}'..='\u{206f}' // general punctuation
+            | '\u{3000}'..='\u{303f}' // CJK punctuation
+            | '\u{ff01}'..='\u{ff0f}' // fullwidth ASCII punctuation
+            | '\u{ff1a}'..='\u{ff20}'
+            | '\u{ff3b}'..='\u{ff40}'
+            | '\u{ff5b}'..='\u{ff65}')
+}
+
+/// 64-bit FNV-1a, which unlike `std`'s hasher is the same across releases.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn term_tag_slugifies_special_chars() {
+        assert_eq!(build_term_tag("taken aback"), "term:taken_aback");
+        assert_eq!(build_term_tag("  Weird-term?! "), "term:weird_term");
+    }
+
+    #[test]
+    fn term_tag_keeps_every_script() {
+        assert_eq!(build_term_tag("Café"), "term:café");
+        // A decomposed "é" normalizes to the same tag.
+        assert_eq!(build_term_tag("cafe\u{301}"), "term:café");
+        assert_eq!(build_term_tag("Привет!"), "term:привет");
+        assert_eq!(build_term_tag("日本、語"), "term:日本_語");
+        assert_eq!(build_term_tag("नमस्ते"), "term:नमस्ते");
+        assert_ne!(build_term_tag("?!"), build_term_tag("…"));
+        assert_eq!(build_term_tag("?!"), build_term_tag(" ?! "));
+        assert!(build_term_tag("?!").starts_with("term:_"));
+    }
+
+    #[test]
+    fn note_keys_read_direction_from_the_production_tag() {
+        let tags = |tags: &[&str]| tags.iter().map(|tag| tag.to_string()).collect();
+        assert_eq!(
+            note_keys(tags(&["en", "auto-generated", "term:apple", "production"])),
+            vec![("term:apple".to_string(), CardDirection::Production)]
+        );
+        assert_eq!(
+            note_keys(tags(&["term:taken_aback", "freq:rare"])),
+            vec![("term:taken_aback".to_string(), CardDirection::Recognition)]
+        );
+        assert!(note_keys(tags(&["auto-generated"])).is_empty());
+    }
+}
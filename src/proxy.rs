@@ -0,0 +1,444 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use anyhow::{Context, Result};
+use axum::{
+    Router,
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, Method, StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::post,
+};
+use serde_json::Value;
+
+use crate::pipeline::{CardDirection, Pipeline};
+
+pub const DEFAULT_LISTEN: &str = "127.0.0.1:8766";
+
+/// Request headers passed on so AnkiConnect can apply its own
+/// `webCorsOriginList` to the page behind the request.
+const FORWARDED_HEADERS: &[header::HeaderName] = &[
+    header::ORIGIN,
+    header::ACCESS_CONTROL_REQUEST_METHOD,
+    header::ACCESS_CONTROL_REQUEST_HEADERS,
+];
+
+/// Marks notes the proxy enriched. They are the extension's notes, so they
+/// don't get the `auto-generated` and `term:` tags that make notaforge treat
+/// a note as its own in duplicate checks and `migrate-tags`.
+const ENRICHED_TAG: &str = "notaforge:enriched";
+
+/// Field names popup dictionaries commonly put the looked-up word in.
+const TERM_FIELD_GUESSES: &[&str] = &["Expression", "Word", "Term", "Vocab", "Front"];
+
+/// How notes passing through the proxy are enriched.
+pub struct ProxyOptions {
+    /// AnkiConnect endpoint requests are forwarded to.
+    pub upstream: String,
+    /// Field holding the term; guessed from common names when unset.
+    pub term_field: Option<String>,
+    /// Field the rendered card back is appended to; notes without it are
+    /// forwarded unchanged.
+    pub enrich_field: String,
+}
+
+struct ProxyState {
+    pipeline: Pipeline,
+    options: ProxyOptions,
+}
+
+/// Listens for AnkiConnect requests until the process is interrupted.
+pub async fn serve(pipeline: Pipeline, options: ProxyOptions, listen: SocketAddr) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(listen)
+        .await
+        .with_context(|| format!("failed to listen on {listen}"))?;
+    println!(
+        "Proxying AnkiConnect on http://{listen} to {}",
+        options.upstream
+    );
+
+    let state = Arc::new(ProxyState { pipeline, options });
+    let router = Router::new()
+        .route("/", post(handle).options(preflight))
+        .with_state(state);
+
+    axum::serve(listener, router)
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await
+        .context("proxy failed")
+}
+
+/// AnkiConnect answers browser preflights itself, so they are forwarded too.
+async fn preflight(State(state): State<Arc<ProxyState>>, headers: HeaderMap) -> Response {
+    respond(
+        forward(
+            &state.pipeline.http,
+            &state.options.upstream,
+            Method::OPTIONS,
+            &headers,
+            Bytes::new(),
+        )
+        .await,
+    )
+}
+
+async fn handle(State(state): State<Arc<ProxyState>>, headers: HeaderMap, body: Bytes) -> Response {
+    let (request, body) = match serde_json::from_slice::<Value>(&body) {
+        Ok(mut request) => {
+            state.enrich_request(&mut request).await;
+            let body = Bytes::from(request.to_string());
+            (Some(request), body)
+        }
+        // Let AnkiConnect produce its own error for malformed requests.
+        Err(_) => (None, body),
+    };
+
+    let forwarded = forward(
+        &state.pipeline.http,
+        &state.options.upstream,
+        Method::POST,
+        &headers,
+        body,
+    )
+    .await;
+    if let (Some(request), Ok((_, _, reply))) = (&request, &forwarded)
+        && let Ok(reply) = serde_json::from_slice::<Value>(reply)
+    {
+        for id in created_note_ids(request, &reply) {
+            if let Err(err) = state.pipeline.journal.created(id) {
+                eprintln!("Warning: note {id} was not recorded for undo: {err:#}");
+            }
+        }
+    }
+    respond(forwarded)
+}
+
+fn respond(forwarded: Result<(StatusCode, HeaderMap, Bytes)>) -> Response {
+    match forwarded {
+        Ok(response) => response.into_response(),
+        Err(err) => (
+            StatusCode::BAD_GATEWAY,
+            axum::Json(serde_json::json!({ "result": null, "error": format!("{err:#}") })),
+        )
+            .into_response(),
+    }
+}
+
+/// Sends the request on with the browser's origin and returns AnkiConnect's
+/// answer, CORS headers included, so the proxy allows exactly the origins
+/// AnkiConnect does.
+async fn forward(
+    client: &reqwest::Client,
+    upstream: &str,
+    method: Method,
+    headers: &HeaderMap,
+    body: Bytes,
+) -> Result<(StatusCode, HeaderMap, Bytes)> {
+    let mut request = client
+        .request(method, upstream)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(body);
+    for name in FORWARDED_HEADERS {
+        if let Some(value) = headers.get(name) {
+            request = request.header(name, value);
+        }
+    }
+    let upstream_response = request.send().await.context("AnkiConnect is unreachable")?;
+
+    let status = StatusCode::from_u16(upstream_response.status().as_u16())
+        .unwrap_or(StatusCode::BAD_GATEWAY);
+    let mut response_headers = HeaderMap::new();
+    for (name, value) in upstream_response.headers() {
+        if *name == header::CONTENT_TYPE
+            || *name == header::VARY
+            || name.as_str().starts_with("access-control-")
+        {
+            response_headers.insert(name, value.clone());
+        }
+    }
+    let bytes = upstream_response
+        .bytes()
+        .await
+        .context("failed to read AnkiConnect response")?;
+
+    Ok((status, response_headers, bytes))
+}
+
+/// IDs of the notes an `addNote`, `addNotes` or `multi` request created,
+/// read from AnkiConnect's reply so `undo` can remove them.
+fn created_note_ids(request: &Value, reply: &Value) -> Vec<u64> {
+    let result = reply.get("result").unwrap_or(&Value::Null);
+    match request.get("action").and_then(Value::as_str) {
+        Some("addNote") => result.as_u64().into_iter().collect(),
+        Some("addNotes") => result
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_u64)
+            .collect(),
+        Some("multi") => {
+            let actions = request.pointer("/params/actions").and_then(Value::as_array);
+            let replies = result.as_array();
+            let (Some(actions), Some(replies)) = (actions, replies) else {
+                return Vec::new();
+            };
+            actions
+                .iter()
+                .zip(replies)
+                .flat_map(|(action, reply)| {
+                    // Nested replies carry their own `result` from API version 6 on.
+                    match reply.get("result") {
+                        Some(_) => created_note_ids(action, reply),
+                        None => created_note_ids(action, &serde_json::json!({ "result": reply })),
+                    }
+                })
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+impl ProxyState {
+    /// Enriches the notes of `addNote`, `addNotes` and the same actions
+    /// nested in `multi`; everything else passes through untouched.
+    async fn enrich_request(&self, request: &mut Value) {
+        let action = request
+            .get("action")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        let Some(params) = request.get_mut("params") else {
+            return;
+        };
+
+        match action.as_str() {
+            "addNote" => {
+                if let Some(note) = params.get_mut("note") {
+                    self.enrich_note(note).await;
+                }
+            }
+            "addNotes" => {
+                if let Some(Value::Array(notes)) = params.get_mut("notes") {
+                    for note in notes {
+                        self.enrich_note(note).await;
+                    }
+                }
+            }
+            "multi" => {
+                if let Some(Value::Array(actions)) = params.get_mut("actions") {
+                    for nested in actions {
+                        Box::pin(self.enrich_request(nested)).await;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// A failed lookup shouldn't stop the note from being added, so errors
+    /// are logged and the note is forwarded as it came in.
+    async fn enrich_note(&self, note: &mut Value) {
+        let Some(term) = note_term(note, self.options.term_field.as_deref()) else {
+            return;
+        };
+        if note
+            .pointer(&format!("/fields/{}", self.options.enrich_field))
+            .is_none()
+        {
+            return;
+        }
+
+        match self.pipeline.lookup(&term, None).await {
+            Ok(card) => {
+                let fields = self.pipeline.render(&card, CardDirection::Recognition);
+                merge_into_note(
+                    note,
+                    &self.options.enrich_field,
+                    &fields.back,
+                    &enrichment_tags(&fields.tags),
+                );
+                println!("Enriched note for '{term}'");
+            }
+            Err(err) => eprintln!("Failed to enrich note for '{term}': {err:#}"),
+        }
+    }
+}
+
+/// The card's tags minus those marking notaforge's own notes, plus
+/// [`ENRICHED_TAG`].
+fn enrichment_tags(tags: &[String]) -> Vec<String> {
+    tags.iter()
+        .filter(|tag| *tag != "auto-generated" && !tag.starts_with("term:"))
+        .cloned()
+        .chain([ENRICHED_TAG.to_string()])
+        .collect()
+}
+
+fn note_term(note: &Value, term_field: Option<&str>) -> Option<String> {
+    let fields = note.get("fields")?.as_object()?;
+    let value = match term_field {
+        Some(name) => fields.get(name)?,
+        None => TERM_FIELD_GUESSES
+            .iter()
+            .find_map(|name| fields.get(*name))?,
+    };
+
+    let term = strip_html(value.as_str()?);
+    (!term.is_empty()).then_some(term)
+}
+
+fn strip_html(value: &str) -> String {
+    let mut text = String::with_capacity(value.len());
+    let mut in_tag = false;
+    for c in value.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.trim().to_string()
+}
+
+/// Appends `html` to the field (keeping what the dictionary filled in) and
+/// adds the tags the note doesn't have yet.
+fn merge_into_note(note: &mut Value, field: &str, html: &str, tags: &[String]) {
+    if let Some(Value::String(existing)) = note.pointer_mut(&format!("/fields/{field}")) {
+        if existing.trim().is_empty() {
+            *existing = html.to_string();
+        } else {
+            existing.push_str("<hr>");
+            existing.push_str(html);
+        }
+    }
+
+    let Some(object) = note.as_object_mut() else {
+        return;
+    };
+    let note_tags = object
+        .entry("tags")
+        .or_insert_with(|| Value::Array(Vec::new()));
+    if let Value::Array(note_tags) = note_tags {
+        for tag in tags {
+            if !note_tags.iter().any(|existing| existing == tag.as_str()) {
+                note_tags.push(Value::String(tag.clone()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn finds_term_in_configured_or_guessed_field() {
+        let note = json!({
+            "fields": { "Expression": "<b>aback</b>", "Meaning": "", "Sentence": "taken aback" }
+        });
+        assert_eq!(note_term(&note, None).as_deref(), Some("aback"));
+        assert_eq!(
+            note_term(&note, Some("Sentence")).as_deref(),
+            Some("taken aback")
+        );
+        assert_eq!(note_term(&note, Some("Missing")), None);
+    }
+
+    #[test]
+    fn enriched_notes_are_not_marked_as_generated() {
+        let tags = ["auto-generated", "term:aback", "en", "notaforge:incomplete"].map(String::from);
+        assert_eq!(
+            enrichment_tags(&tags),
+            ["en", "notaforge:incomplete", ENRICHED_TAG]
+        );
+    }
+
+    #[test]
+    fn merges_enrichment_after_dictionary_content() {
+        let mut note = json!({
+            "fields": { "Expression": "aback", "Meaning": "backwards" },
+            "tags": ["yomitan"]
+        });
+        merge_into_note(
+            &mut note,
+            "Meaning",
+            "<div>назад</div>",
+            &["yomitan".to_string(), "term:aback".to_string()],
+        );
+
+        assert_eq!(note["fields"]["Meaning"], "backwards<hr><div>назад</div>");
+        assert_eq!(note["tags"], json!(["yomitan", "term:aback"]));
+    }
+
+    #[tokio::test]
+    async fn leaves_cors_decisions_to_anki_connect() {
+        // Stands in for AnkiConnect with its default `webCorsOriginList`.
+        async fn upstream(headers: HeaderMap) -> Response {
+            let origin = headers.get(header::ORIGIN).cloned();
+            match origin {
+                Some(origin) if origin == "http://localhost" => {
+                    ([(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin)], "{}").into_response()
+                }
+                _ => "{}".into_response(),
+            }
+        }
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            axum::serve(listener, Router::new().route("/", post(upstream)))
+                .await
+                .unwrap();
+        });
+
+        let client = reqwest::Client::new();
+        let request = |origin: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ORIGIN, origin.parse().unwrap());
+            let (client, url) = (&client, &url);
+            async move {
+                forward(client, url, Method::POST, &headers, Bytes::new())
+                    .await
+                    .unwrap()
+            }
+        };
+
+        let (_, allowed, _) = request("http://localhost").await;
+        assert_eq!(
+            allowed[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "http://localhost"
+        );
+        let (_, foreign, _) = request("https://evil.example").await;
+        assert!(foreign.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+    }
+
+    #[test]
+    fn reads_created_note_ids_from_replies() {
+        let add = json!({ "action": "addNote", "params": { "note": {} } });
+        assert_eq!(
+            created_note_ids(&add, &json!({ "result": 7, "error": null })),
+            [7]
+        );
+        assert!(
+            created_note_ids(&add, &json!({ "result": null, "error": "duplicate" })).is_empty()
+        );
+
+        let multi = json!({
+            "action": "multi",
+            "params": { "actions": [
+                { "action": "findNotes", "params": { "query": "deck:English" } },
+                { "action": "addNotes", "params": { "notes": [{}, {}] } },
+                { "action": "addNote", "params": { "note": {} } },
+            ] }
+        });
+        let reply = json!({ "result": [
+            { "result": [1, 2], "error": null },
+            { "result": [8, null], "error": null },
+            9,
+        ], "error": null });
+        assert_eq!(created_note_ids(&multi, &reply), [8, 9]);
+    }
+}
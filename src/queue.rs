@@ -0,0 +1,419 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use ankiconnect_rs::{Deck, Model};
+use anyhow::{Context, Result};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::{Deserialize, Serialize};
+
+use crate::anki::{AnkiConnect, find_deck, find_model};
+use crate::card_template::CardFields;
+use crate::duplicates::Duplicates;
+use crate::error::ErrorKind;
+use crate::journal::Journal;
+use crate::pipeline::{CardDirection, submit_note};
+
+/// File name of the queue inside the data directory.
+pub const QUEUE_FILE: &str = "pending-notes.json";
+
+/// A fully built note waiting for Anki to become reachable.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingNote {
+    pub term: String,
+    pub term_tag: String,
+    pub direction: CardDirection,
+    pub deck: String,
+    pub model: String,
+    /// Model fields receiving `fields.front` and `fields.back`.
+    pub front_field: String,
+    pub back_field: String,
+    pub fields: CardFields,
+    pub media: Vec<MediaFile>,
+    /// Unix time the note was queued.
+    pub queued_at: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaKind {
+    Sound,
+    Image,
+}
+
+/// A file to upload to Anki's media folder and reference from `field`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MediaFile {
+    pub field: String,
+    pub filename: String,
+    pub kind: MediaKind,
+    /// File contents, base64 encoded so the queue stays plain JSON.
+    pub data: String,
+}
+
+impl MediaFile {
+    pub fn new(field: &str, filename: String, kind: MediaKind, data: &[u8]) -> Self {
+        Self {
+            field: field.to_string(),
+            filename,
+            kind,
+            data: STANDARD.encode(data),
+        }
+    }
+
+    /// Markup embedding the file under the name Anki stored it as.
+    pub fn html(&self, stored: &str) -> String {
+        match self.kind {
+            MediaKind::Sound => format!("[sound:{stored}]"),
+            MediaKind::Image => format!("<img src=\"{stored}\">"),
+        }
+    }
+}
+
+/// Notes built while AnkiConnect was unreachable, kept in the data directory
+/// until `sync` pushes them.
+///
+/// Changes take an exclusive lock on `<queue>.lock`, so processes sharing the
+/// data directory, e.g. `sync` next to `watch-clipboard`, don't lose each
+/// other's notes.
+pub struct NoteQueue {
+    path: PathBuf,
+}
+
+impl NoteQueue {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn load(&self) -> Result<Vec<PendingNote>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let raw = fs::read_to_string(&self.path)
+            .with_context(|| format!("failed to read '{}'", self.path.display()))?;
+        serde_json::from_str(&raw)
+            .with_context(|| format!("failed to parse '{}'", self.path.display()))
+    }
+
+    /// Runs `change` on the queued notes and saves the result, holding the
+    /// queue lock throughout.
+    pub fn update<T>(&self, change: impl FnOnce(&mut Vec<PendingNote>) -> T) -> Result<T> {
+        let _lock = self.lock()?;
+        let mut notes = self.load()?;
+        let result = change(&mut notes);
+        self.save(&notes)?;
+        Ok(result)
+    }
+
+    /// Replaces the notes of an earlier [`NoteQueue::load`] with `left`,
+    /// keeping notes queued since.
+    pub fn replace(&self, snapshot: &[PendingNote], left: Vec<PendingNote>) -> Result<()> {
+        self.update(|notes| {
+            notes.retain(|note| !snapshot.contains(note));
+            notes.splice(0..0, left);
+        })
+    }
+
+    /// Blocks until no other writer holds the queue; released on drop.
+    fn lock(&self) -> Result<File> {
+        let path = self.path.with_extension("lock");
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create '{}'", parent.display()))?;
+        }
+        let file =
+            File::create(&path).with_context(|| format!("failed to open '{}'", path.display()))?;
+        file.lock()
+            .with_context(|| format!("failed to lock '{}'", path.display()))?;
+        Ok(file)
+    }
+
+    /// Replaces the queue with `notes`, removing the file once it is empty.
+    fn save(&self, notes: &[PendingNote]) -> Result<()> {
+        if notes.is_empty() {
+            if self.path.exists() {
+                fs::remove_file(&self.path)
+                    .with_context(|| format!("failed to remove '{}'", self.path.display()))?;
+            }
+            return Ok(());
+        }
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create '{}'", parent.display()))?;
+        }
+        // A temp file per write, so two writers never rename each other's
+        // half-written file into place.
+        static WRITES: AtomicU64 = AtomicU64::new(0);
+        let temp = self.path.with_extension(format!(
+            "{}-{}.tmp",
+            process::id(),
+            WRITES.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&temp, serde_json::to_string_pretty(notes)?)
+            .and_then(|()| fs::rename(&temp, &self.path))
+            .inspect_err(|_| {
+                let _ = fs::remove_file(&temp);
+            })
+            .with_context(|| format!("failed to write '{}'", self.path.display()))
+    }
+
+    /// Whether a note for the same term, direction and deck is already queued.
+    pub fn contains(&self, deck: &str, term_tag: &str, direction: CardDirection) -> Result<bool> {
+        Ok(self.load()?.iter().any(|note| {
+            note.deck == deck && note.term_tag == term_tag && note.direction == direction
+        }))
+    }
+
+    /// Appends a note, returning the new queue length.
+    pub fn push(&self, mut note: PendingNote) -> Result<usize> {
+        note.queued_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        self.update(|notes| {
+            notes.push(note);
+            notes.len()
+        })
+    }
+}
+
+/// Whether a failed AnkiConnect check means notes should wait in the queue.
+/// Only an unreachable Anki qualifies: a rejected API key or an outdated
+/// add-on would fail every `sync` as well, so those stay errors.
+pub fn is_offline(err: &anyhow::Error) -> bool {
+    ErrorKind::of(err) == Some(ErrorKind::AnkiUnreachable)
+}
+
+/// Pushes queued notes to Anki with the usual duplicate check, printing one
+/// line per note. Notes that fail stay queued; returns how many are left.
+pub async fn sync(
+    client: &AnkiConnect,
+    queue: &NoteQueue,
+    duplicates: &Duplicates,
+    journal: &Journal,
+) -> Result<usize> {
+    let snapshot = queue.load()?;
+    let notes = snapshot.clone();
+    if notes.is_empty() {
+        println!("No queued notes.");
+        return Ok(0);
+    }
+    client.check().await?;
+
+    let mut decks: HashMap<String, Deck> = HashMap::new();
+    let mut models: HashMap<String, Model> = HashMap::new();
+    let mut stored = HashMap::new();
+    let mut left = Vec::new();
+    let mut notes = notes.into_iter();
+    while let Some(note) = notes.next() {
+        let mut warnings = Vec::new();
+        let result = async {
+            if !decks.contains_key(&note.deck) {
+                decks.insert(note.deck.clone(), find_deck(client, &note.deck).await?);
+            }
+            if !models.contains_key(&note.model) {
+                models.insert(note.model.clone(), find_model(client, &note.model).await?);
+            }
+            let query = duplicates.query(&note.deck, &note.term_tag, note.direction);
+            if !client.find_cards(&query).await?.is_empty()
+                || !duplicates
+                    .find_handmade(client, &note.deck, &[&note.term])
+                    .await?
+                    .is_empty()
+            {
+                return Ok(None);
+            }
+            let (deck, model) = (&decks[&note.deck], &models[&note.model]);
+            submit_note(
+                client,
+                deck,
+                model,
+                &note,
+                duplicates.scope,
+                &mut stored,
+                &mut warnings,
+            )
+            .await
+            .map(|(note_id, _)| note_id)
+        }
+        .await;
+
+        for warning in warnings {
+            eprintln!("Warning for '{}': {warning}", note.term);
+        }
+        match result {
+            Ok(Some(id)) => {
+                println!("Added note for '{}' with ID: {id}", note.term);
+                if let Err(err) = journal.created(id) {
+                    eprintln!("Warning: note {id} was not recorded for undo: {err:#}");
+                }
+            }
+            Ok(None) => println!(
+                "{} for term '{}' already exists in {}; dropping it.",
+                note.direction.note_label(),
+                note.term,
+                duplicates.describe(&note.deck)
+            ),
+            Err(err) if is_offline(&err) => {
+                eprintln!("Failed to sync '{}': {err:#}", note.term);
+                left.push(note);
+                left.extend(notes.by_ref());
+            }
+            Err(err) => {
+                eprintln!("Failed to sync '{}': {err:#}", note.term);
+                left.push(note);
+            }
+        }
+    }
+
+    let remaining = left.len();
+    queue.replace(&snapshot, left)?;
+    if remaining > 0 {
+        eprintln!(
+            "{remaining} note(s) remain queued in '{}'.",
+            queue.path().display()
+        );
+    }
+    Ok(remaining)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pending(term: &str, direction: CardDirection) -> PendingNote {
+        PendingNote {
+            term: term.to_string(),
+            term_tag: format!("term:{term}"),
+            direction,
+            deck: "English".to_string(),
+            model: "Basic".to_string(),
+            front_field: "Front".to_string(),
+            back_field: "Back".to_string(),
+            fields: CardFields {
+                front: term.to_string(),
+                back: "back".to_string(),
+                tags: vec![format!("term:{term}")],
+            },
+            media: vec![MediaFile::new(
+                "Back",
+                format!("{term}.jpg"),
+                MediaKind::Image,
+                b"jpeg",
+            )],
+            queued_at: 0,
+        }
+    }
+
+    #[test]
+    fn round_trips_notes_and_removes_the_file_when_drained() {
+        let dir = tempfile::tempdir().unwrap();
+        let queue = NoteQueue::new(dir.path().join("data").join(QUEUE_FILE));
+        assert!(queue.load().unwrap().is_empty());
+
+        assert_eq!(
+            queue
+                .push(pending("apple", CardDirection::Recognition))
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            queue
+                .push(pending("apple", CardDirection::Production))
+                .unwrap(),
+            2
+        );
+        assert!(
+            queue
+                .contains("English", "term:apple", CardDirection::Production)
+                .unwrap()
+        );
+        assert!(
+            !queue
+                .contains("Deutsch", "term:apple", CardDirection::Production)
+                .unwrap()
+        );
+
+        let notes = queue.load().unwrap();
+        assert_eq!(notes[0].media[0].data, "anBlZw==");
+        assert_eq!(
+            notes[0].media[0].html("apple.jpg"),
+            "<img src=\"apple.jpg\">"
+        );
+        assert!(notes[0].queued_at > 0);
+        let temp_files = fs::read_dir(dir.path().join("data"))
+            .unwrap()
+            .filter(|entry| {
+                entry
+                    .as_ref()
+                    .unwrap()
+                    .path()
+                    .extension()
+                    .is_some_and(|ext| ext == "tmp")
+            })
+            .count();
+        assert_eq!(temp_files, 0, "temp files are renamed into place");
+
+        queue.save(&[]).unwrap();
+        assert!(!queue.path().exists());
+    }
+
+    #[test]
+    fn keeps_notes_pushed_while_syncing() {
+        let dir = tempfile::tempdir().unwrap();
+        let queue = NoteQueue::new(dir.path().join(QUEUE_FILE));
+        queue
+            .push(pending("apple", CardDirection::Recognition))
+            .unwrap();
+        queue
+            .push(pending("pear", CardDirection::Recognition))
+            .unwrap();
+
+        let snapshot = queue.load().unwrap();
+        // Another process queues a note while `sync` talks to Anki.
+        let other = NoteQueue::new(queue.path().to_path_buf());
+        other
+            .push(pending("plum", CardDirection::Recognition))
+            .unwrap();
+        queue.replace(&snapshot, vec![snapshot[1].clone()]).unwrap();
+
+        let terms: Vec<String> = queue
+            .load()
+            .unwrap()
+            .into_iter()
+            .map(|note| note.term)
+            .collect();
+        assert_eq!(terms, ["pear", "plum"]);
+    }
+
+    #[tokio::test]
+    async fn only_unreachable_anki_goes_offline() {
+        let kind = |kind| anyhow::Error::new(kind).context("AnkiConnect at http://127.0.0.1:8765");
+        assert!(is_offline(&kind(ErrorKind::AnkiUnreachable)));
+        assert!(!is_offline(&kind(ErrorKind::AnkiRejected)));
+        assert!(!is_offline(&kind(ErrorKind::MissingDeckOrModel)));
+        assert!(!is_offline(&anyhow::anyhow!("AnkiConnect version failed")));
+
+        // Some other web server on AnkiConnect's port.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let router = axum::Router::new().route(
+            "/",
+            axum::routing::post(|| async { "<html>It works!</html>" }),
+        );
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        let client = AnkiConnect::new(&url, None, std::time::Duration::from_secs(2)).unwrap();
+        let err = client.check().await.unwrap_err();
+        assert_eq!(ErrorKind::of(&err), Some(ErrorKind::AnkiRejected));
+        assert!(!is_offline(&err));
+    }
+}
@@ -0,0 +1,208 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use anyhow::{Context, Result};
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::card_template::{CardFields, ExampleSentence};
+use crate::error::ErrorKind;
+use crate::output::{OutputFormat, print_report};
+use crate::pipeline::{CardDirection, Pipeline, TermReport};
+
+pub const DEFAULT_LISTEN: &str = "127.0.0.1:8731";
+
+/// Body of `POST /cards`.
+#[derive(Debug, Deserialize)]
+pub struct CardRequest {
+    pub term: String,
+    /// Sentence to use as the only example instead of looking examples up.
+    pub sentence: Option<String>,
+    /// Translation of `sentence`; translated automatically when omitted.
+    pub translation: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PreviewQuery {
+    term: String,
+}
+
+#[derive(Serialize)]
+struct PreviewNote {
+    direction: CardDirection,
+    #[serde(flatten)]
+    fields: CardFields,
+}
+
+#[derive(Serialize)]
+struct PreviewResponse {
+    term: String,
+    notes: Vec<PreviewNote>,
+}
+
+/// Errors become `{"error": "..."}` with a matching status code.
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(err: anyhow::Error) -> Self {
+        let status = ErrorKind::of(&err).map_or(StatusCode::INTERNAL_SERVER_ERROR, status_of);
+        ApiError(status, format!("{err:#}"))
+    }
+}
+
+/// The HTTP counterpart of [`ErrorKind::exit_code`].
+fn status_of(kind: ErrorKind) -> StatusCode {
+    match kind {
+        ErrorKind::AnkiUnreachable => StatusCode::SERVICE_UNAVAILABLE,
+        ErrorKind::MissingDeckOrModel => StatusCode::NOT_FOUND,
+        ErrorKind::AnkiRejected | ErrorKind::ProviderFailed => StatusCode::BAD_GATEWAY,
+    }
+}
+
+/// Serves the pipeline until the process is interrupted.
+pub async fn serve(pipeline: Pipeline, listen: SocketAddr) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(listen)
+        .await
+        .with_context(|| format!("failed to listen on {listen}"))?;
+    println!("Listening on http://{listen}");
+
+    axum::serve(listener, router(Arc::new(pipeline)))
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await
+        .context("server failed")
+}
+
+fn router(pipeline: Arc<Pipeline>) -> Router {
+    Router::new()
+        .route("/health", get(health))
+        .route("/preview", get(preview))
+        .route("/cards", post(add_card))
+        .with_state(pipeline)
+}
+
+async fn health(State(pipeline): State<Arc<Pipeline>>) -> Json<serde_json::Value> {
+    let anki = pipeline.client.check().await.is_ok();
+    Json(json!({
+        "status": "ok",
+        "anki": anki,
+        "deck": pipeline.deck.name(),
+        "model": pipeline.model.name(),
+    }))
+}
+
+async fn preview(
+    State(pipeline): State<Arc<Pipeline>>,
+    Query(query): Query<PreviewQuery>,
+) -> Result<Json<PreviewResponse>, ApiError> {
+    let term = require_term(&query.term)?;
+    let card = pipeline.lookup(term, None).await?;
+    let notes = pipeline
+        .directions()
+        .into_iter()
+        .map(|direction| PreviewNote {
+            direction,
+            fields: pipeline.render(&card, direction),
+        })
+        .collect();
+
+    Ok(Json(PreviewResponse {
+        term: term.to_string(),
+        notes,
+    }))
+}
+
+async fn add_card(
+    State(pipeline): State<Arc<Pipeline>>,
+    Json(request): Json<CardRequest>,
+) -> Result<Json<TermReport>, ApiError> {
+    let term = require_term(&request.term)?;
+    let report = pipeline.add_term(term, request_context(&request)).await?;
+    print_report(
+        OutputFormat::Text,
+        &pipeline.duplicates.describe(pipeline.deck.name()),
+        &report,
+    );
+
+    Ok(Json(report))
+}
+
+fn require_term(term: &str) -> Result<&str, ApiError> {
+    let term = term.trim();
+    if term.is_empty() {
+        return Err(ApiError(
+            StatusCode::BAD_REQUEST,
+            "term must not be empty".to_string(),
+        ));
+    }
+    Ok(term)
+}
+
+fn request_context(request: &CardRequest) -> Option<ExampleSentence> {
+    let sentence = request.sentence.as_deref()?.trim();
+    if sentence.is_empty() {
+        return None;
+    }
+
+    Some(ExampleSentence {
+        sentence: sentence.to_string(),
+        translation: request
+            .translation
+            .as_deref()
+            .map(str::trim)
+            .unwrap_or_default()
+            .to_string(),
+        ..ExampleSentence::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn card_request_sentence_becomes_context() {
+        let request: CardRequest = serde_json::from_str(
+            r#"{"term": "aback", "sentence": " I was taken aback. ", "translation": "Я опешил."}"#,
+        )
+        .unwrap();
+        let context = request_context(&request).unwrap();
+
+        assert_eq!(context.sentence, "I was taken aback.");
+        assert_eq!(context.translation, "Я опешил.");
+        assert!(context.highlight.is_empty());
+    }
+
+    #[test]
+    fn rejects_blank_terms() {
+        assert!(require_term("  ").is_err());
+        assert_eq!(require_term(" aback ").ok(), Some("aback"));
+
+        let request: CardRequest = serde_json::from_str(r#"{"term": "aback"}"#).unwrap();
+        assert!(request_context(&request).is_none());
+    }
+
+    #[test]
+    fn error_kinds_map_to_status_codes() {
+        let unreachable = anyhow::Error::new(ErrorKind::AnkiUnreachable).context("Anki is closed");
+        let ApiError(status, message) = unreachable.into();
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(message.starts_with("Anki is closed"));
+
+        let ApiError(status, _) = anyhow::anyhow!("unexpected").into();
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
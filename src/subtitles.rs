@@ -0,0 +1,119 @@
+use std::time::Duration;
+
+/// One subtitle line with its timing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cue {
+    /// 1-based position in the file (the SRT counter when present).
+    pub index: usize,
+    pub start: Duration,
+    pub end: Duration,
+    /// Text with markup removed and line breaks joined by spaces.
+    pub text: String,
+}
+
+/// Parses SRT or WebVTT subtitles; blocks without a timing line are skipped.
+pub fn parse(raw: &str) -> Vec<Cue> {
+    let normalized = raw.replace("\r\n", "\n").replace('\u{feff}', "");
+    let mut cues = Vec::new();
+
+    for block in normalized.split("\n\n") {
+        let lines: Vec<&str> = block.lines().map(str::trim).collect();
+        let Some(timing_at) = lines.iter().position(|line| line.contains("-->")) else {
+            continue;
+        };
+
+        let Some((start, end)) = parse_timing(lines[timing_at]) else {
+            continue;
+        };
+
+        let index = timing_at
+            .checked_sub(1)
+            .and_then(|at| lines[at].parse().ok())
+            .unwrap_or(cues.len() + 1);
+
+        let text = lines[timing_at + 1..]
+            .iter()
+            .map(|line| strip_markup(line))
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        if !text.is_empty() {
+            cues.push(Cue {
+                index,
+                start,
+                end,
+                text,
+            });
+        }
+    }
+
+    cues
+}
+
+fn parse_timing(line: &str) -> Option<(Duration, Duration)> {
+    let (start, rest) = line.split_once("-->")?;
+    // WebVTT may append cue settings such as `align:start` after the end time.
+    let end = rest.split_whitespace().next()?;
+    Some((parse_timestamp(start.trim())?, parse_timestamp(end)?))
+}
+
+/// Accepts `HH:MM:SS,mmm` (SRT) and `[HH:]MM:SS.mmm` (WebVTT).
+fn parse_timestamp(value: &str) -> Option<Duration> {
+    let (clock, millis) = value.split_once([',', '.']).unwrap_or((value, "0"));
+    let millis: u64 = millis.parse().ok()?;
+
+    let mut seconds = 0u64;
+    for part in clock.split(':') {
+        seconds = seconds * 60 + part.parse::<u64>().ok()?;
+    }
+
+    Some(Duration::from_millis(seconds * 1000 + millis))
+}
+
+/// Removes HTML-like tags (`<i>`, `<c.yellow>`) and ASS overrides (`{\an8}`).
+fn strip_markup(line: &str) -> String {
+    let mut text = String::with_capacity(line.len());
+    let mut depth: Option<char> = None;
+
+    for c in line.chars() {
+        match (depth, c) {
+            (None, '<') => depth = Some('>'),
+            (None, '{') => depth = Some('}'),
+            (Some(close), c) if c == close => depth = None,
+            (Some(_), _) => {}
+            (None, c) => text.push(c),
+        }
+    }
+
+    text.trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_srt_cues() {
+        let raw = "1\r\n00:00:01,500 --> 00:00:03,000\r\n<i>I was taken</i>\r\naback.\r\n\r\n2\r\n00:01:02,000 --> 00:01:04,250\r\n{\\an8}Really?\r\n";
+        let cues = parse(raw);
+
+        assert_eq!(cues.len(), 2);
+        assert_eq!(cues[0].text, "I was taken aback.");
+        assert_eq!(cues[0].start, Duration::from_millis(1_500));
+        assert_eq!(cues[1].index, 2);
+        assert_eq!(cues[1].end, Duration::from_millis(64_250));
+        assert_eq!(cues[1].text, "Really?");
+    }
+
+    #[test]
+    fn parses_webvtt_with_settings() {
+        let raw = "WEBVTT\n\nNOTE a comment\n\n00:05.000 --> 00:07.500 align:start\n<c.yellow>Hello</c> there\n";
+        let cues = parse(raw);
+
+        assert_eq!(cues.len(), 1);
+        assert_eq!(cues[0].index, 1);
+        assert_eq!(cues[0].start, Duration::from_millis(5_000));
+        assert_eq!(cues[0].text, "Hello there");
+    }
+}
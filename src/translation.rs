@@ -0,0 +1,507 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow};
+use futures::future::join_all;
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::{
+    sync::{Mutex, Semaphore},
+    time::Instant,
+};
+
+use crate::base_health::BaseHealth;
+
+pub const DEFAULT_BASES: &[&str] = &[
+    "https://lingva.ml/api/v1",
+    "https://lingva.garudalinux.org/api/v1",
+    "https://translate.plausible.stream/api/v1",
+];
+
+pub const DEFAULT_CONCURRENCY: usize = 4;
+pub const DEFAULT_REQUESTS_PER_SECOND: f64 = 2.0;
+
+/// Prefix marking a LibreTranslate server in `translation_bases`.
+const LIBRE_PREFIX: &str = "libre:";
+
+/// Lingva puts the whole batch into the URL path, so keep it short.
+const LINGVA_BATCH_CHARS: usize = 1_000;
+const LIBRE_BATCH_CHARS: usize = 5_000;
+
+/// Lingva has no batch endpoint, but Google keeps line breaks, so a batch is
+/// sent as one newline-joined text and split again.
+const LINGVA_DELIMITER: char = '\n';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    Lingva,
+    LibreTranslate,
+}
+
+impl Backend {
+    fn name(self) -> &'static str {
+        match self {
+            Backend::Lingva => "lingva",
+            Backend::LibreTranslate => "libretranslate",
+        }
+    }
+}
+
+/// A translated text and the base that produced it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Translation {
+    pub text: String,
+    /// Provider label such as `lingva@lingva.ml`.
+    pub served_by: String,
+}
+
+struct TranslationBase {
+    url: String,
+    backend: Backend,
+    /// Earliest time the next request to this base may start.
+    next_slot: Mutex<Instant>,
+}
+
+impl TranslationBase {
+    /// `libre:https://host` selects LibreTranslate; anything else is a
+    /// Lingva API root such as `https://lingva.ml/api/v1`.
+    fn parse(spec: &str) -> Self {
+        let (backend, url) = match spec.strip_prefix(LIBRE_PREFIX) {
+            Some(url) => (Backend::LibreTranslate, url),
+            None => (Backend::Lingva, spec),
+        };
+        Self {
+            url: url.trim().trim_end_matches('/').to_string(),
+            backend,
+            next_slot: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Backend and host, e.g. `lingva@lingva.ml`, used in provenance.
+    fn label(&self) -> String {
+        let host = self
+            .url
+            .split_once("://")
+            .map_or(&*self.url, |(_, rest)| rest);
+        let host = host.split('/').next().unwrap_or(host);
+        format!("{}@{host}", self.backend.name())
+    }
+
+    fn batch_chars(&self) -> usize {
+        match self.backend {
+            Backend::Lingva => LINGVA_BATCH_CHARS,
+            Backend::LibreTranslate => LIBRE_BATCH_CHARS,
+        }
+    }
+
+    /// Waits until this base may be called again and books the next slot.
+    async fn wait_turn(&self, interval: Duration) {
+        let mut next = self.next_slot.lock().await;
+        tokio::time::sleep_until(*next).await;
+        *next = Instant::now() + interval;
+    }
+}
+
+/// Translates text through the configured bases, batching strings, keeping
+/// request rates polite and preferring bases that have been healthy.
+pub struct Translator {
+    client: Client,
+    bases: Vec<TranslationBase>,
+    retries: u32,
+    backoff_ms: u64,
+    /// Minimum spacing between two requests to the same base.
+    interval: Duration,
+    /// Caps requests in flight across all bases.
+    permits: Semaphore,
+    health: BaseHealth,
+}
+
+impl Translator {
+    pub fn new(
+        client: Client,
+        bases: &[String],
+        retries: u32,
+        backoff_ms: u64,
+        concurrency: usize,
+        requests_per_second: f64,
+        health: BaseHealth,
+    ) -> Self {
+        let bases = if bases.is_empty() {
+            DEFAULT_BASES
+                .iter()
+                .map(|base| TranslationBase::parse(base))
+                .collect()
+        } else {
+            bases
+                .iter()
+                .map(|base| TranslationBase::parse(base))
+                .collect()
+        };
+        let interval = if requests_per_second > 0.0 {
+            Duration::from_secs_f64(1.0 / requests_per_second)
+        } else {
+            Duration::ZERO
+        };
+
+        Self {
+            client,
+            bases,
+            retries,
+            backoff_ms,
+            interval,
+            permits: Semaphore::new(concurrency.max(1)),
+            health,
+        }
+    }
+
+    /// Translates every text, returning one result per input in order.
+    ///
+    /// Texts are grouped into as few requests as the backend allows; a batch
+    /// that fails on every base fails all of its texts.
+    pub async fn translate_batch(
+        &self,
+        texts: &[String],
+        source_lang: &str,
+        target_lang: &str,
+    ) -> Vec<Result<Translation>> {
+        let mut results: Vec<Option<Result<Translation>>> = texts
+            .iter()
+            .map(|text| {
+                text.trim().is_empty().then(|| {
+                    Ok(Translation {
+                        text: String::new(),
+                        served_by: String::new(),
+                    })
+                })
+            })
+            .collect();
+
+        let pending: Vec<usize> = (0..texts.len())
+            .filter(|&index| results[index].is_none())
+            .collect();
+        let budget = self
+            .bases
+            .iter()
+            .map(TranslationBase::batch_chars)
+            .min()
+            .unwrap_or(LINGVA_BATCH_CHARS);
+        let chunks = chunk_by_length(&pending, |&index| texts[index].len(), budget);
+
+        let translated = join_all(chunks.iter().map(|chunk| {
+            let batch: Vec<String> = chunk
+                .iter()
+                .map(|&index| texts[index].replace(['\r', '\n'], " "))
+                .collect();
+            async move { self.translate_chunk(&batch, source_lang, target_lang).await }
+        }))
+        .await;
+
+        for (chunk, outcome) in chunks.iter().zip(translated) {
+            match outcome {
+                Ok((values, served_by)) => {
+                    for (&index, value) in chunk.iter().zip(values) {
+                        results[index] = Some(match value {
+                            Ok(text) if text.trim().is_empty() => {
+                                Err(anyhow!("translation came back empty"))
+                            }
+                            Ok(text) => Ok(Translation {
+                                text,
+                                served_by: served_by.clone(),
+                            }),
+                            Err(err) => Err(err),
+                        });
+                    }
+                }
+                Err(err) => {
+                    for &index in chunk {
+                        results[index] = Some(Err(anyhow!("{err:#}")));
+                    }
+                }
+            }
+        }
+
+        if let Err(err) = self.health.save() {
+            eprintln!("Warning: could not save translation base health: {err:#}");
+        }
+
+        results
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|| Err(anyhow!("text was not translated"))))
+            .collect()
+    }
+
+    /// Tries the bases healthiest first until one translates the chunk,
+    /// returning a result per text and the label of that base.
+    async fn translate_chunk(
+        &self,
+        texts: &[String],
+        source_lang: &str,
+        target_lang: &str,
+    ) -> Result<(Vec<Result<String>>, String)> {
+        let urls: Vec<&str> = self.bases.iter().map(|base| base.url.as_str()).collect();
+        let mut last_error = None;
+        for index in self.health.order(&urls) {
+            let base = &self.bases[index];
+            match self
+                .translate_with_base(base, texts, source_lang, target_lang)
+                .await
+            {
+                Ok(values) => return Ok((values, base.label())),
+                Err(err) => last_error = Some(err.context(format!("{} failed", base.url))),
+            }
+        }
+
+        Err(match last_error {
+            Some(err) => err.context("every translation base failed"),
+            None => anyhow!("no translation base configured"),
+        })
+    }
+
+    /// Fails as a whole when the base is unusable; texts it could not
+    /// translate one by one fail on their own.
+    async fn translate_with_base(
+        &self,
+        base: &TranslationBase,
+        texts: &[String],
+        source_lang: &str,
+        target_lang: &str,
+    ) -> Result<Vec<Result<String>>> {
+        match base.backend {
+            Backend::LibreTranslate => {
+                let request = self
+                    .client
+                    .post(format!("{}/translate", base.url))
+                    .json(&json!({
+                        "q": texts,
+                        "source": source_lang,
+                        "target": target_lang,
+                        "format": "text",
+                    }));
+
+                #[derive(Deserialize)]
+                struct LibreResponse {
+                    #[serde(rename = "translatedText")]
+                    translated_text: Vec<String>,
+                }
+
+                let parsed: LibreResponse = self
+                    .send(base, request)
+                    .await?
+                    .json()
+                    .await
+                    .context("LibreTranslate response parsing failed")?;
+                if parsed.translated_text.len() != texts.len() {
+                    return Err(anyhow!(
+                        "LibreTranslate returned {} translations for {} texts",
+                        parsed.translated_text.len(),
+                        texts.len()
+                    ));
+                }
+                Ok(parsed.translated_text.into_iter().map(Ok).collect())
+            }
+            Backend::Lingva => {
+                let joined = texts.join(&LINGVA_DELIMITER.to_string());
+                let translated = self
+                    .lingva_request(base, &joined, source_lang, target_lang)
+                    .await?;
+                if let Some(values) = split_batch(&translated, texts.len()) {
+                    return Ok(values.into_iter().map(Ok).collect());
+                }
+
+                // The translation merged or split lines; fall back to one
+                // request per text rather than guessing the alignment.
+                let mut single = join_all(
+                    texts
+                        .iter()
+                        .map(|text| self.lingva_request(base, text, source_lang, target_lang)),
+                )
+                .await;
+                // Nothing came through, so let the next base try instead.
+                if !single.is_empty() && single.iter().all(Result::is_err) {
+                    return Err(single.swap_remove(0).unwrap_err());
+                }
+                Ok(single)
+            }
+        }
+    }
+
+    async fn lingva_request(
+        &self,
+        base: &TranslationBase,
+        text: &str,
+        source_lang: &str,
+        target_lang: &str,
+    ) -> Result<String> {
+        #[derive(Deserialize)]
+        struct LingvaResponse {
+            translation: String,
+        }
+
+        let url = format!(
+            "{}/{}/{}/{}",
+            base.url,
+            source_lang,
+            target_lang,
+            urlencoding::encode(text)
+        );
+        let parsed: LingvaResponse = self
+            .send(base, self.client.get(url))
+            .await?
+            .json()
+            .await
+            .context("Lingva response parsing failed")?;
+        Ok(parsed.translation)
+    }
+
+    /// Sends a request within the concurrency and rate limits, retrying
+    /// failures and 429s with growing backoff until the base gets ejected.
+    async fn send(&self, base: &TranslationBase, request: RequestBuilder) -> Result<Response> {
+        let mut attempt = 0;
+        let mut delay = self.backoff_ms.max(200);
+
+        loop {
+            let attempt_request = request
+                .try_clone()
+                .ok_or_else(|| anyhow!("translation request cannot be retried"))?;
+
+            let (result, latency) = {
+                let _permit = self.permits.acquire().await?;
+                base.wait_turn(self.interval).await;
+                let started = Instant::now();
+                let result = attempt_request.send().await;
+                (result, started.elapsed())
+            };
+
+            let err = match result {
+                Ok(response) if response.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    self.health.record_rate_limited(&base.url);
+                    anyhow!("rate limited (429)")
+                }
+                Ok(response) => match response.error_for_status() {
+                    Ok(response) => {
+                        self.health.record_success(&base.url, latency);
+                        return Ok(response);
+                    }
+                    Err(err) => {
+                        self.health.record_failure(&base.url);
+                        anyhow::Error::new(err).context("translation service returned error")
+                    }
+                },
+                Err(err) => {
+                    self.health.record_failure(&base.url);
+                    anyhow::Error::new(err).context("translation request failed")
+                }
+            };
+
+            if attempt >= self.retries || self.health.is_ejected(&base.url) {
+                return Err(err);
+            }
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+            attempt += 1;
+            delay = (delay as f64 * 1.5).round() as u64;
+        }
+    }
+}
+
+/// Groups items so each group's total length stays within `budget`; an item
+/// longer than the budget gets a group of its own.
+fn chunk_by_length<T: Copy>(items: &[T], len: impl Fn(&T) -> usize, budget: usize) -> Vec<Vec<T>> {
+    let mut chunks: Vec<Vec<T>> = Vec::new();
+    let mut current_len = 0;
+
+    for item in items {
+        let item_len = len(item) + 1;
+        match chunks.last_mut() {
+            Some(chunk) if current_len + item_len <= budget => {
+                chunk.push(*item);
+                current_len += item_len;
+            }
+            _ => {
+                chunks.push(vec![*item]);
+                current_len = item_len;
+            }
+        }
+    }
+
+    chunks
+}
+
+/// Splits a batched Lingva translation, or `None` when the line count no
+/// longer matches the input.
+fn split_batch(translated: &str, expected: usize) -> Option<Vec<String>> {
+    let values: Vec<String> = translated
+        .split(LINGVA_DELIMITER)
+        .map(|line| line.trim().to_string())
+        .collect();
+    (values.len() == expected).then_some(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_backend_from_base_spec() {
+        let lingva = TranslationBase::parse("https://lingva.ml/api/v1/");
+        assert_eq!(lingva.backend, Backend::Lingva);
+        assert_eq!(lingva.url, "https://lingva.ml/api/v1");
+
+        let libre = TranslationBase::parse("libre:http://localhost:5000");
+        assert_eq!(libre.backend, Backend::LibreTranslate);
+        assert_eq!(libre.url, "http://localhost:5000");
+        assert_eq!(libre.label(), "libretranslate@localhost:5000");
+    }
+
+    #[test]
+    fn chunks_texts_within_budget() {
+        let lengths = [4, 4, 4, 20, 3];
+        let chunks = chunk_by_length(&[0, 1, 2, 3, 4], |&index| lengths[index], 12);
+        assert_eq!(chunks, vec![vec![0, 1], vec![2], vec![3], vec![4]]);
+    }
+
+    #[test]
+    fn splits_batched_translation_only_when_aligned() {
+        assert_eq!(
+            split_batch("врасплох\n удивлённый \nназад", 3),
+            Some(vec![
+                "врасплох".to_string(),
+                "удивлённый".to_string(),
+                "назад".to_string()
+            ])
+        );
+        assert_eq!(split_batch("врасплох удивлённый", 2), None);
+    }
+
+    #[tokio::test]
+    async fn lingva_fallback_keeps_translations_of_other_texts() {
+        // Merges batched lines, so every text is retried on its own.
+        async fn lingva(
+            axum::extract::Path((_, _, text)): axum::extract::Path<(String, String, String)>,
+        ) -> axum::response::Response {
+            use axum::response::IntoResponse;
+            match text.as_str() {
+                "bad" => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+                text if text.contains('\n') => {
+                    axum::Json(json!({ "translation": "merged" })).into_response()
+                }
+                text => axum::Json(json!({ "translation": text.to_uppercase() })).into_response(),
+            }
+        }
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let router =
+                axum::Router::new().route("/{source}/{target}/{text}", axum::routing::get(lingva));
+            axum::serve(listener, router).await.unwrap();
+        });
+
+        let translator =
+            Translator::new(Client::new(), &[base], 0, 0, 1, 0.0, BaseHealth::default());
+        let texts = ["good", "bad", "fine"].map(String::from);
+        let results = translator.translate_batch(&texts, "en", "de").await;
+
+        assert_eq!(results[0].as_ref().unwrap().text, "GOOD");
+        assert!(results[1].is_err());
+        assert_eq!(results[2].as_ref().unwrap().text, "FINE");
+    }
+}
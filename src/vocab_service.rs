@@ -5,31 +5,81 @@ use futures::future::join_all;
 use reqwest::Client;
 use serde::Deserialize;
 
-use crate::card_template::{ExampleSentence, VocabularyCard};
+use crate::card_template::{
+    ExampleSentence, FieldSource, FieldStatus, TranslationSection, VocabularyCard,
+};
+use crate::error::ErrorKind;
+use crate::translation::{Translation, Translator};
 
 const DICTIONARY_ENDPOINT: &str = "https://api.dictionaryapi.dev/api/v2/entries/en/";
 const DATAMUSE_ENDPOINT: &str = "https://api.datamuse.com/words";
 const TATOEBA_ENDPOINT: &str = "https://tatoeba.org/en/api_v0/search";
-const DEFAULT_TRANSLATE_BASES: &[&str] = &[
-    "https://lingva.ml/api/v1",
-    "https://lingva.garudalinux.org/api/v1",
-    "https://translate.plausible.stream/api/v1",
-];
 
+pub const DEFAULT_MAX_EXAMPLES: usize = 3;
+
+/// Provider names recorded in field provenance.
+/// Translations are credited to the base that served them instead.
+const DICTIONARY_PROVIDER: &str = "dictionaryapi.dev";
+
+/// Lookup settings shared by every card built during a run.
+pub struct LookupOptions {
+    pub source_lang: String,
+    /// Languages to translate into; examples use the first one.
+    pub target_langs: Vec<String>,
+    pub max_examples: usize,
+    pub translator: Translator,
+}
+
+impl LookupOptions {
+    fn primary_target(&self) -> &str {
+        self.target_langs
+            .first()
+            .map(String::as_str)
+            .unwrap_or_default()
+    }
+}
+
+/// Looks the term up and assembles a card.
+///
+/// When `context` is given (e.g. the sentence the word was mined from), it
+/// becomes the card's only example instead of dictionary and Tatoeba ones.
 pub async fn build_vocabulary_card(
     client: &Client,
     term: &str,
-    source_lang: &str,
-    target_lang: &str,
-    translate_bases: &[String],
-    translate_retries: u32,
-    translate_backoff_ms: u64,
+    context: Option<ExampleSentence>,
+    options: &LookupOptions,
 ) -> Result<VocabularyCard> {
-    let (dictionary_res, datamuse_res) = tokio::join!(
+    let source_lang = options.source_lang.as_str();
+    // Tatoeba is skipped rather than asked in another language.
+    let tatoeba_from = tatoeba_lang(source_lang);
+
+    let fetch_examples = async {
+        match (&context, tatoeba_from) {
+            (None, Some(from)) => fetch_tatoeba_examples(client, term, from, options).await,
+            _ => Ok(Vec::new()),
+        }
+    };
+    let (dictionary_res, datamuse_res, tatoeba_res) = tokio::join!(
         fetch_dictionary_entry(client, term),
-        fetch_datamuse_synonyms(client, term)
+        fetch_datamuse_synonyms(client, term),
+        fetch_examples
     );
 
+    let mut warnings = Vec::new();
+    for (name, result) in [
+        ("dictionary", dictionary_res.as_ref().err()),
+        ("datamuse", datamuse_res.as_ref().err()),
+        ("tatoeba", tatoeba_res.as_ref().err()),
+    ] {
+        if let Some(err) = result {
+            warnings.push(format!("{name}: {err:#}"));
+        }
+    }
+
+    let dictionary_ok = dictionary_res.is_ok();
+    let datamuse_ok = datamuse_res.is_ok();
+    let tatoeba_ok = tatoeba_res.is_ok();
+
     let dictionary = dictionary_res.unwrap_or_default();
     let mut synonyms_set: BTreeSet<String> = dictionary.synonyms.iter().cloned().collect();
     let datamuse_synonyms = datamuse_res.unwrap_or_default();
@@ -37,111 +87,456 @@ pub async fn build_vocabulary_card(
     let synonyms: Vec<String> = synonyms_set.into_iter().collect();
 
     let part_of_speech = dictionary.part_of_speech.unwrap_or_default();
+    let etymology = dictionary.origin.unwrap_or_default();
     let pronunciation = dictionary.pronunciation.unwrap_or_default();
 
-    let base_candidates: Vec<String> = if translate_bases.is_empty() {
-        DEFAULT_TRANSLATE_BASES
-            .iter()
-            .map(|base| base.to_string())
-            .collect()
-    } else {
-        translate_bases.iter().cloned().collect()
+    let definition_text = dictionary.definition.unwrap_or_default();
+
+    let from_context = context.is_some();
+    let mut examples = match context {
+        Some(example) => vec![example],
+        None => {
+            let mut candidates: Vec<ExampleSentence> = dictionary
+                .examples
+                .into_iter()
+                .map(|sentence| ExampleSentence {
+                    sentence,
+                    ..Default::default()
+                })
+                .collect();
+            candidates.extend(tatoeba_res.unwrap_or_default());
+            rank_examples(candidates, term, options.max_examples)
+        }
+    };
+    // Dictionary examples come without a translation, so fill the gaps.
+    let untranslated: Vec<usize> = (0..examples.len())
+        .filter(|&index| examples[index].translation.is_empty())
+        .collect();
+
+    // One batch per target language: term, definition, synonyms, and for the
+    // primary language the examples.
+    let input = SectionInput {
+        term,
+        definition: &definition_text,
+        synonyms: &synonyms,
+        dictionary_ok,
+        datamuse_ok,
     };
-    let base_slice = &base_candidates;
+    let batches = join_all(
+        options
+            .target_langs
+            .iter()
+            .enumerate()
+            .map(|(position, lang)| {
+                let mut texts = input.texts();
+                if position == 0 {
+                    texts.extend(
+                        untranslated
+                            .iter()
+                            .map(|&index| examples[index].sentence.clone()),
+                    );
+                }
+                async move {
+                    options
+                        .translator
+                        .translate_batch(&texts, source_lang, lang)
+                        .await
+                }
+            }),
+    )
+    .await;
+
+    let mut sources = SourceLog::default();
+    let mut translations = Vec::with_capacity(batches.len());
+    let mut primary_translated = false;
+    let mut example_results = Vec::new();
+    for (position, (lang, results)) in options.target_langs.iter().zip(batches).enumerate() {
+        let mut results = results.into_iter();
+        let primary = position == 0;
+        let (section, translated) = build_section(
+            lang,
+            primary,
+            &input,
+            &mut results,
+            &mut sources,
+            &mut warnings,
+        );
+        if primary {
+            primary_translated = translated;
+            example_results = results.collect();
+        }
+        translations.push(section);
+    }
 
-    let synonyms_joined = synonyms.join(", ");
-    let translated_synonyms = if synonyms_joined.is_empty() {
-        String::new()
-    } else {
-        let futures = synonyms.iter().map(|synonym| {
-            translate_text(
-                client,
-                synonym,
-                source_lang,
-                target_lang,
-                base_slice,
-                translate_retries,
-                translate_backoff_ms,
-            )
-        });
-        let results = join_all(futures).await;
+    if !dictionary_ok && !datamuse_ok && !primary_translated {
+        return Err(anyhow::Error::new(ErrorKind::ProviderFailed)
+            .context(format!("No lookup succeeded for '{term}'")));
+    }
 
-        let mut translated = Vec::with_capacity(synonyms.len());
-        for (original, result) in synonyms.iter().zip(results) {
-            match result {
-                Ok(value) if !value.trim().is_empty() => translated.push(value),
-                _ => translated.push(original.clone()),
+    let mut untranslated_examples = 0;
+    let mut examples_served_by = None;
+    let mut example_results = example_results.into_iter();
+    for &index in &untranslated {
+        match next_translation(&mut example_results) {
+            Ok(value) => {
+                examples[index].translation = value.text.trim().to_string();
+                examples_served_by.get_or_insert(value.served_by);
             }
+            Err(_) => untranslated_examples += 1,
         }
-        translated.join(", ")
-    };
+    }
 
-    let definition_text = dictionary
-        .definition
-        .unwrap_or_else(|| format!("No definition found for {term}."));
-
-    let (translation_res, usage_res) = tokio::join!(
-        translate_text(
-            client,
-            term,
-            source_lang,
-            target_lang,
-            base_slice,
-            translate_retries,
-            translate_backoff_ms,
+    for field in ["pronunciation", "part_of_speech", "etymology"] {
+        if dictionary_ok {
+            sources.record(field, &[(DICTIONARY_PROVIDER, true)], FieldStatus::Ok, None);
+        } else {
+            sources.record(
+                field,
+                &[],
+                FieldStatus::Missing,
+                Some("dictionary lookup failed".to_string()),
+            );
+        }
+    }
+
+    let example_providers = [
+        ("context", from_context),
+        (DICTIONARY_PROVIDER, !from_context && dictionary_ok),
+        (
+            "tatoeba",
+            !from_context && tatoeba_from.is_some() && tatoeba_ok,
         ),
-        translate_text(
-            client,
-            &definition_text,
-            source_lang,
-            target_lang,
-            base_slice,
-            translate_retries,
-            translate_backoff_ms,
-        )
+        (
+            examples_served_by.as_deref().unwrap_or_default(),
+            examples_served_by.is_some(),
+        ),
+    ];
+    let unsupported = (!from_context && tatoeba_from.is_none())
+        .then(|| format!("Tatoeba does not support '{source_lang}'"));
+    let (status, note) = examples_status(
+        examples.len(),
+        !from_context && (!dictionary_ok || !tatoeba_ok),
+        untranslated_examples,
+        unsupported,
     );
+    let providers: &[(&str, bool)] = if examples.is_empty() {
+        &[]
+    } else {
+        &example_providers
+    };
+    sources.record("examples", providers, status, note);
+
+    for example in &mut examples {
+        if example.highlight.is_empty() {
+            example.highlight = find_highlight(&example.sentence, term);
+        }
+    }
+
+    let mut extra_tags = vec![source_lang.to_string()];
+    extra_tags.extend(options.target_langs.iter().cloned());
+    extra_tags.push("auto-generated".to_string());
 
-    let translation = match translation_res {
-        Ok(value) if !value.trim().is_empty() => value,
-        Ok(_) => term.to_string(),
-        Err(_) => term.to_string(),
+    Ok(VocabularyCard {
+        term: term.to_string(),
+        pronunciation,
+        part_of_speech,
+        examples,
+        translations,
+        etymology,
+        frequency_rank: None,
+        cefr_level: None,
+        extra_tags,
+        sources: sources.0,
+        warnings,
+    })
+}
+
+/// Source-language material each target language is translated from.
+struct SectionInput<'a> {
+    term: &'a str,
+    definition: &'a str,
+    synonyms: &'a [String],
+    dictionary_ok: bool,
+    datamuse_ok: bool,
+}
+
+impl SectionInput<'_> {
+    /// Texts to translate, in the order `build_section` reads them back.
+    fn texts(&self) -> Vec<String> {
+        let mut texts = vec![self.term.to_string(), self.definition.to_string()];
+        texts.extend(self.synonyms.iter().cloned());
+        texts
+    }
+}
+
+/// Collects field provenance while a card is assembled.
+#[derive(Default)]
+struct SourceLog(Vec<FieldSource>);
+
+impl SourceLog {
+    fn record(
+        &mut self,
+        field: impl Into<String>,
+        providers: &[(&str, bool)],
+        status: FieldStatus,
+        note: Option<String>,
+    ) {
+        self.0.push(FieldSource {
+            field: field.into(),
+            providers: providers
+                .iter()
+                .filter(|(_, used)| *used)
+                .map(|(name, _)| name.to_string())
+                .collect(),
+            status,
+            note,
+        });
+    }
+}
+
+/// How the examples field was filled. Finding no example is not a failure;
+/// only a failed lookup leaves the field [`FieldStatus::Missing`].
+fn examples_status(
+    found: usize,
+    lookup_failed: bool,
+    untranslated: usize,
+    unsupported: Option<String>,
+) -> (FieldStatus, Option<String>) {
+    if found == 0 && lookup_failed {
+        return (
+            FieldStatus::Missing,
+            Some("example lookups failed".to_string()),
+        );
+    }
+    if found == 0 {
+        return (
+            FieldStatus::Empty,
+            Some(unsupported.unwrap_or_else(|| "no examples found".to_string())),
+        );
+    }
+    if untranslated > 0 {
+        let note = format!("{untranslated} examples left untranslated");
+        return (
+            FieldStatus::Fallback,
+            Some(match unsupported {
+                Some(unsupported) => format!("{note}; {unsupported}"),
+                None => note,
+            }),
+        );
+    }
+    (FieldStatus::Ok, unsupported)
+}
+
+fn next_translation(
+    results: &mut impl Iterator<Item = Result<Translation>>,
+) -> Result<Translation> {
+    results
+        .next()
+        .unwrap_or_else(|| Err(anyhow!("text was not translated")))
+}
+
+/// Builds the translated fields of one target language from its batch
+/// results, returning whether the term itself was translated.
+///
+/// Fields of extra languages are recorded as e.g. `translation.uk`.
+fn build_section(
+    lang: &str,
+    primary: bool,
+    input: &SectionInput,
+    results: &mut impl Iterator<Item = Result<Translation>>,
+    sources: &mut SourceLog,
+    warnings: &mut Vec<String>,
+) -> (TranslationSection, bool) {
+    let field = |name: &str| {
+        if primary {
+            name.to_string()
+        } else {
+            format!("{name}.{lang}")
+        }
     };
 
-    let translated_usage = match usage_res {
-        Ok(value) if !value.trim().is_empty() => value,
-        _ => definition_text.clone(),
+    let translation_res = next_translation(results);
+    let usage_res = next_translation(results);
+
+    let mut untranslated_synonyms = 0;
+    let mut synonyms_served_by = None;
+    let mut translated = Vec::with_capacity(input.synonyms.len());
+    for original in input.synonyms {
+        match next_translation(results) {
+            Ok(value) => {
+                synonyms_served_by.get_or_insert(value.served_by);
+                translated.push(value.text);
+            }
+            Err(_) => {
+                untranslated_synonyms += 1;
+                translated.push(original.clone());
+            }
+        }
+    }
+
+    if let Err(err) = &translation_res {
+        warnings.push(format!("{}: {err:#}", field("translation")));
+    }
+    let term_translated = translation_res.is_ok();
+
+    let heading = match translation_res {
+        Ok(value) => {
+            sources.record(
+                field("translation"),
+                &[(value.served_by.as_str(), true)],
+                FieldStatus::Ok,
+                None,
+            );
+            value.text
+        }
+        Err(_) => {
+            sources.record(
+                field("translation"),
+                &[],
+                FieldStatus::Fallback,
+                Some("translation failed; showing the term itself".to_string()),
+            );
+            input.term.to_string()
+        }
     };
 
-    let example_sentence = match dictionary.example {
-        Some(ex) => ex,
-        None => fetch_tatoeba_example(client, term)
-            .await
-            .unwrap_or_default(),
+    let usage = match (input.dictionary_ok, usage_res) {
+        (false, _) => {
+            sources.record(
+                field("definition"),
+                &[],
+                FieldStatus::Missing,
+                Some("dictionary lookup failed".to_string()),
+            );
+            String::new()
+        }
+        (true, Ok(value)) => {
+            let translated = !input.definition.is_empty();
+            sources.record(
+                field("definition"),
+                &[
+                    (DICTIONARY_PROVIDER, true),
+                    (value.served_by.as_str(), translated),
+                ],
+                FieldStatus::Ok,
+                None,
+            );
+            value.text
+        }
+        (true, Err(_)) => {
+            sources.record(
+                field("definition"),
+                &[(DICTIONARY_PROVIDER, true)],
+                FieldStatus::Fallback,
+                Some("definition left untranslated".to_string()),
+            );
+            input.definition.to_string()
+        }
     };
 
-    let highlight = if example_sentence.contains(term) {
-        term.to_string()
+    let synonym_providers = [
+        (DICTIONARY_PROVIDER, input.dictionary_ok),
+        ("datamuse", input.datamuse_ok),
+        (
+            synonyms_served_by.as_deref().unwrap_or_default(),
+            synonyms_served_by.is_some(),
+        ),
+    ];
+    if !input.dictionary_ok && !input.datamuse_ok {
+        sources.record(
+            field("synonyms"),
+            &[],
+            FieldStatus::Missing,
+            Some("dictionary and Datamuse lookups failed".to_string()),
+        );
+    } else if untranslated_synonyms > 0 {
+        sources.record(
+            field("synonyms"),
+            &synonym_providers,
+            FieldStatus::Fallback,
+            Some(format!(
+                "{untranslated_synonyms} synonyms left untranslated"
+            )),
+        );
     } else {
-        String::new()
+        sources.record(field("synonyms"), &synonym_providers, FieldStatus::Ok, None);
+    }
+
+    let section = TranslationSection {
+        lang: lang.to_string(),
+        heading,
+        synonyms: translated.join(", "),
+        usage,
     };
+    (section, term_translated)
+}
 
-    Ok(VocabularyCard {
-        term: term.to_string(),
-        pronunciation,
-        part_of_speech,
-        example: ExampleSentence {
-            sentence: example_sentence,
-            highlight,
-        },
-        translation_heading: translation,
-        translation_synonyms: translated_synonyms,
-        translation_usage: translated_usage,
-        extra_tags: vec![
-            source_lang.to_string(),
-            target_lang.to_string(),
-            "auto-generated".to_string(),
-        ],
-    })
+/// Orders example candidates so that sentences containing the term come
+/// first and shorter sentences beat longer ones, dropping duplicates.
+fn rank_examples(
+    candidates: Vec<ExampleSentence>,
+    term: &str,
+    limit: usize,
+) -> Vec<ExampleSentence> {
+    let mut seen = BTreeSet::new();
+    let mut unique: Vec<ExampleSentence> = candidates
+        .into_iter()
+        .filter(|example| !example.sentence.trim().is_empty())
+        .filter(|example| seen.insert(example.sentence.trim().to_lowercase()))
+        .collect();
+
+    unique.sort_by_key(|example| {
+        (
+            find_highlight(&example.sentence, term).is_empty(),
+            example.sentence.chars().count(),
+        )
+    });
+    unique.truncate(limit);
+    unique
+}
+
+/// Finds the term inside the sentence ignoring ASCII case and returns the
+/// span as it is written there, e.g. "Aback" for the term "aback".
+fn find_highlight(sentence: &str, term: &str) -> String {
+    if term.is_empty() {
+        return String::new();
+    }
+    if sentence.contains(term) {
+        return term.to_string();
+    }
+
+    let haystack = sentence.as_bytes();
+    let needle = term.as_bytes();
+    haystack
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle))
+        .and_then(|start| sentence.get(start..start + needle.len()))
+        .map(str::to_string)
+        .unwrap_or_default()
+}
+
+/// Maps the two-letter codes used for translation to Tatoeba's ISO 639-3 codes.
+fn tatoeba_lang(code: &str) -> Option<&str> {
+    let mapped = match code {
+        "en" => "eng",
+        "ru" => "rus",
+        "uk" => "ukr",
+        "de" => "deu",
+        "es" => "spa",
+        "fr" => "fra",
+        "it" => "ita",
+        "pt" => "por",
+        "pl" => "pol",
+        "nl" => "nld",
+        "tr" => "tur",
+        "ja" => "jpn",
+        "zh" => "cmn",
+        "ko" => "kor",
+        "ar" => "ara",
+        other if other.len() == 3 => other,
+        _ => return None,
+    };
+    Some(mapped)
 }
 
 async fn fetch_dictionary_entry(client: &Client, term: &str) -> Result<DictionaryData> {
@@ -179,12 +574,13 @@ async fn fetch_dictionary_entry(client: &Client, term: &str) -> Result<Dictionar
         .iter()
         .find_map(|def| (!def.definition.is_empty()).then(|| def.definition.clone()));
 
-    // Search ALL meanings for an example, not just the first one
-    let example = entry
+    // Search ALL meanings for examples, not just the first one
+    let examples = entry
         .meanings
         .iter()
         .flat_map(|m| m.definitions.iter())
-        .find_map(|def| def.example.clone());
+        .filter_map(|def| def.example.clone())
+        .collect();
 
     let synonyms = collect_synonyms(&definitions, meaning.synonyms.clone());
 
@@ -192,8 +588,9 @@ async fn fetch_dictionary_entry(client: &Client, term: &str) -> Result<Dictionar
         pronunciation,
         part_of_speech: meaning.part_of_speech.clone(),
         definition,
-        example,
+        examples,
         synonyms,
+        origin: entry.origin.clone(),
     })
 }
 
@@ -213,10 +610,24 @@ async fn fetch_datamuse_synonyms(client: &Client, term: &str) -> Result<Vec<Stri
     Ok(response.into_iter().map(|entry| entry.word).collect())
 }
 
-async fn fetch_tatoeba_example(client: &Client, term: &str) -> Result<String> {
+async fn fetch_tatoeba_examples(
+    client: &Client,
+    term: &str,
+    from: &str,
+    options: &LookupOptions,
+) -> Result<Vec<ExampleSentence>> {
+    let to = tatoeba_lang(options.primary_target());
+    // Fetch a few extra so ranking has something to choose from.
+    let limit = (options.max_examples * 3).max(1).to_string();
+
+    let mut query = vec![("from", from), ("query", term), ("limit", limit.as_str())];
+    if let Some(to) = to {
+        query.push(("to", to));
+    }
+
     let response: TatoebaResponse = client
         .get(TATOEBA_ENDPOINT)
-        .query(&[("from", "eng"), ("query", term), ("limit", "1")])
+        .query(&query)
         .send()
         .await
         .context("Tatoeba request failed")?
@@ -226,133 +637,29 @@ async fn fetch_tatoeba_example(client: &Client, term: &str) -> Result<String> {
         .await
         .context("Tatoeba response parsing failed")?;
 
-    response
+    Ok(response
         .results
         .into_iter()
-        .next()
-        .map(|r| r.text)
-        .ok_or_else(|| anyhow!("No Tatoeba example for '{term}'"))
-}
-
-async fn translate_text(
-    client: &Client,
-    text: &str,
-    source_lang: &str,
-    target_lang: &str,
-    translate_bases: &[String],
-    retries: u32,
-    backoff_ms: u64,
-) -> Result<String> {
-    if text.trim().is_empty() {
-        return Ok(String::new());
-    }
-
-    let base_candidates: Vec<String> = if translate_bases.is_empty() {
-        DEFAULT_TRANSLATE_BASES
-            .iter()
-            .map(|base| base.to_string())
-            .collect()
-    } else {
-        translate_bases.iter().cloned().collect()
-    };
-
-    for base in base_candidates {
-        match translate_with_base(
-            client,
-            text,
-            source_lang,
-            target_lang,
-            &base,
-            retries,
-            backoff_ms,
-        )
-        .await
-        {
-            Ok(result) if !result.trim().is_empty() => return Ok(result),
-            Ok(_) => continue,
-            Err(_err) => continue,
-        }
-    }
-
-    Ok(String::new())
-}
-
-async fn translate_with_base(
-    client: &Client,
-    text: &str,
-    source_lang: &str,
-    target_lang: &str,
-    base: &str,
-    retries: u32,
-    backoff_ms: u64,
-) -> Result<String> {
-    if text.trim().is_empty() {
-        return Ok(String::new());
-    }
-
-    #[derive(Deserialize)]
-    struct LingvaResponse {
-        translation: String,
-    }
-
-    let base = base.trim_end_matches('/');
-    let url = format!(
-        "{}/{}/{}/{}",
-        base,
-        source_lang,
-        target_lang,
-        urlencoding::encode(text)
-    );
-
-    let mut attempt = 0;
-    let mut delay = backoff_ms.max(200);
-
-    loop {
-        let response = client.get(&url).send().await;
-        match response {
-            Ok(resp) => {
-                if resp.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
-                    if attempt < retries {
-                        tokio::time::sleep(std::time::Duration::from_millis(delay)).await;
-                        attempt += 1;
-                        delay = (delay as f64 * 1.5).round() as u64;
-                        continue;
-                    } else {
-                        return Ok(String::new());
-                    }
-                }
-
-                match resp.error_for_status() {
-                    Ok(success) => {
-                        let parsed: LingvaResponse = success
-                            .json()
-                            .await
-                            .context("Lingva response parsing failed")?;
-                        return Ok(parsed.translation);
-                    }
-                    Err(err) => {
-                        if attempt < retries {
-                            tokio::time::sleep(std::time::Duration::from_millis(delay)).await;
-                            attempt += 1;
-                            delay = (delay as f64 * 1.5).round() as u64;
-                            continue;
-                        } else {
-                            return Err(err).context("Lingva returned error");
-                        }
-                    }
-                }
+        .map(|result| {
+            // Direct translations come first, indirect ones after.
+            let translation = to
+                .and_then(|to| {
+                    result
+                        .translations
+                        .iter()
+                        .flatten()
+                        .find(|t| t.lang.as_deref() == Some(to))
+                })
+                .map(|t| t.text.clone())
+                .unwrap_or_default();
+
+            ExampleSentence {
+                sentence: result.text,
+                highlight: String::new(),
+                translation,
             }
-            Err(err) => {
-                if attempt < retries {
-                    tokio::time::sleep(std::time::Duration::from_millis(delay)).await;
-                    attempt += 1;
-                    delay = (delay as f64 * 1.5).round() as u64;
-                    continue;
-                }
-                return Err(err).context("Lingva request failed");
-            }
-        }
-    }
+        })
+        .collect())
 }
 
 fn collect_synonyms(definitions: &[Definition], base_synonyms: Vec<String>) -> Vec<String> {
@@ -370,6 +677,8 @@ fn collect_synonyms(definitions: &[Definition], base_synonyms: Vec<String>) -> V
 struct DictionaryEntry {
     phonetic: Option<String>,
     #[serde(default)]
+    origin: Option<String>,
+    #[serde(default)]
     phonetics: Vec<Phonetic>,
     #[serde(default)]
     meanings: Vec<Meaning>,
@@ -415,6 +724,15 @@ struct TatoebaResponse {
 #[derive(Deserialize)]
 struct TatoebaSentence {
     text: String,
+    #[serde(default)]
+    translations: Vec<Vec<TatoebaTranslation>>,
+}
+
+#[derive(Deserialize)]
+struct TatoebaTranslation {
+    text: String,
+    #[serde(default)]
+    lang: Option<String>,
 }
 
 #[derive(Default)]
@@ -422,6 +740,75 @@ struct DictionaryData {
     pronunciation: Option<String>,
     part_of_speech: Option<String>,
     definition: Option<String>,
-    example: Option<String>,
+    examples: Vec<String>,
     synonyms: Vec<String>,
+    origin: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example(sentence: &str) -> ExampleSentence {
+        ExampleSentence {
+            sentence: sentence.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn ranks_examples_by_term_presence_then_length() {
+        let ranked = rank_examples(
+            vec![
+                example("A rather long sentence that never mentions the word."),
+                example("She was taken aback by the long and unexpected question."),
+                example("Aback, he stepped."),
+                example("aback, he stepped."),
+                example("Short one."),
+            ],
+            "aback",
+            3,
+        );
+
+        let sentences: Vec<&str> = ranked.iter().map(|e| e.sentence.as_str()).collect();
+        assert_eq!(
+            sentences,
+            vec![
+                "Aback, he stepped.",
+                "She was taken aback by the long and unexpected question.",
+                "Short one.",
+            ]
+        );
+    }
+
+    #[test]
+    fn highlight_keeps_surface_case() {
+        assert_eq!(find_highlight("Aback, he stepped.", "aback"), "Aback");
+        assert_eq!(find_highlight("Привет, aback!", "ABACK"), "aback");
+        assert_eq!(find_highlight("Nothing here.", "aback"), "");
+    }
+
+    #[test]
+    fn examples_degrade_only_when_a_lookup_failed() {
+        assert_eq!(
+            examples_status(0, false, 0, None),
+            (FieldStatus::Empty, Some("no examples found".to_string()))
+        );
+        let unsupported = Some("Tatoeba does not support 'sv'".to_string());
+        assert_eq!(
+            examples_status(0, false, 0, unsupported.clone()),
+            (FieldStatus::Empty, unsupported)
+        );
+        assert_eq!(examples_status(0, true, 0, None).0, FieldStatus::Missing);
+        assert_eq!(examples_status(2, true, 1, None).0, FieldStatus::Fallback);
+        assert_eq!(examples_status(2, false, 0, None), (FieldStatus::Ok, None));
+        assert!(!FieldStatus::Empty.is_degraded());
+    }
+
+    #[test]
+    fn unmapped_languages_have_no_tatoeba_code() {
+        assert_eq!(tatoeba_lang("de"), Some("deu"));
+        assert_eq!(tatoeba_lang("epo"), Some("epo"));
+        assert_eq!(tatoeba_lang("sv"), None);
+    }
 }